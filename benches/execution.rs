@@ -0,0 +1,151 @@
+/*
+    Tiny BASIC interpreter written in Rust
+    Copyright (C) 2025 Artyom Makarov
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+//! Compares running a program, whose lines are parsed once into trees when they
+//! are stored, with the character-level executor which the interpreter had before,
+//! and which read the source of a line every time it was executed. That executor
+//! no longer exists, so its times were recorded by running the same programs with
+//! `Interpreter::run` at commit fc46fdf, the last one before the trees, on the
+//! machine that produced [`CHARACTER_LEVEL`]; re-record them there before comparing
+//! on another machine.
+//!
+//! Executing the lines one by one in the immediate mode, which tokenizes and parses
+//! a line every time, shows the cost of parsing alone. The programs also run as
+//! bytecode, and the lines of a long program generated by machine are walked through.
+//!
+//! Run with `cargo bench`.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use ascii::AsciiStr;
+use rust_tiny_basic::{BufferConsole, Engine, Interpreter, ProgramStorage};
+
+const RUNS: u32 = 200;
+
+/// Times of the character-level executor at commit fc46fdf, built with `cargo bench`
+struct CharacterLevel {
+    straight_line: Duration,
+    for_loop: Duration,
+    long_program: Duration,
+}
+
+const CHARACTER_LEVEL: CharacterLevel = CharacterLevel {
+    straight_line: Duration::from_micros(165),
+    for_loop: Duration::from_micros(14_500),
+    long_program: Duration::from_micros(150_000),
+};
+
+/// Straight-line code, so that executing it line by line has the same effect as running it
+fn straight_line_source() -> String {
+    (0..100)
+        .map(|i| match i % 4 {
+            0 => format!("LET A = (B + {}) * 3 / 2 - {}", i, i),
+            1 => "LET B = A - B / 2 + 1".to_owned(),
+            2 => "IF A > B THEN LET C = A - B : LET D = C * 2".to_owned(),
+            _ => "LET E = ABS(A - 100) + MAX(B, C, 1)".to_owned(),
+        })
+        .enumerate()
+        .map(|(i, line)| format!("{} {}\n", (i + 1) * 10, line))
+        .collect()
+}
+
+const LONG_PROGRAM_LINES: usize = 10000;
+
+/// A long program, each of whose lines is executed once
+fn long_source() -> String {
+    (0..LONG_PROGRAM_LINES)
+        .map(|i| format!("{} LET A = A + {} - B\n", i + 1, i % 2))
+        .collect()
+}
+
+fn print_speed_up(name: &str, before: Duration, after: Duration) {
+    println!("speed-up {}: {:.1}x", name, before.as_secs_f64() / after.as_secs_f64());
+}
+
+fn measure(name: &str, mut run: impl FnMut()) -> Duration {
+    run();
+    let start = Instant::now();
+    for _ in 0..RUNS {
+        run();
+    }
+    let elapsed = start.elapsed() / RUNS;
+    println!("{:<40}{:>12.2?}", name, elapsed);
+    elapsed
+}
+
+fn main() {
+    let source = straight_line_source();
+    let program = ProgramStorage::load(&source).unwrap();
+    let lines: Vec<&AsciiStr> = program
+        .iter()
+        .map(|(_, line)| line)
+        .collect();
+
+    let parsed_once = measure("trees parsed once", || {
+        let mut interpreter = Interpreter::with_console(BufferConsole::new());
+        interpreter.run(&program).unwrap();
+        black_box(interpreter.get_variable("E"));
+    });
+    let parsed_every_time = measure("trees parsed on every execution", || {
+        let mut interpreter = Interpreter::with_console(BufferConsole::new());
+        for line in &lines {
+            interpreter.execute(line, &program).unwrap();
+        }
+        black_box(interpreter.get_variable("E"));
+    });
+    print_speed_up("from parsing once", parsed_every_time, parsed_once);
+    print_speed_up("over the character-level executor", CHARACTER_LEVEL.straight_line, parsed_once);
+    measure("bytecode", || {
+        let mut interpreter = Interpreter::with_console(BufferConsole::new());
+        Engine::Bytecode.run(&mut interpreter, &program).unwrap();
+        black_box(interpreter.get_variable("E"));
+    });
+
+    let loop_program = ProgramStorage::load("10 FOR I = 1 TO 10000\n20 LET S = (S + I) / 2\n30 NEXT I").unwrap();
+    let for_loop = measure("FOR loop of 10000 iterations, tree", || {
+        let mut interpreter = Interpreter::with_console(BufferConsole::new());
+        Engine::Tree.run(&mut interpreter, &loop_program).unwrap();
+        black_box(interpreter.get_variable("S"));
+    });
+    print_speed_up("over the character-level executor", CHARACTER_LEVEL.for_loop, for_loop);
+    measure("FOR loop of 10000 iterations, bytecode", || {
+        let mut interpreter = Interpreter::with_console(BufferConsole::new());
+        Engine::Bytecode.run(&mut interpreter, &loop_program).unwrap();
+        black_box(interpreter.get_variable("S"));
+    });
+
+    let long_program = ProgramStorage::load(&long_source()).unwrap();
+    let long_run = measure(&format!("{} lines, tree", LONG_PROGRAM_LINES), || {
+        let mut interpreter = Interpreter::with_console(BufferConsole::new());
+        Engine::Tree.run(&mut interpreter, &long_program).unwrap();
+        black_box(interpreter.get_variable("A"));
+    });
+    print_speed_up("over the character-level executor", CHARACTER_LEVEL.long_program, long_run);
+    measure(&format!("{} lines, bytecode", LONG_PROGRAM_LINES), || {
+        let mut interpreter = Interpreter::with_console(BufferConsole::new());
+        Engine::Bytecode.run(&mut interpreter, &long_program).unwrap();
+        black_box(interpreter.get_variable("A"));
+    });
+    measure(&format!("walk through {} lines", LONG_PROGRAM_LINES), || {
+        let mut line = long_program.get_first_line_index();
+        while let Some(current) = line {
+            line = long_program.get_following_line_index(black_box(current));
+        }
+    });
+}
@@ -0,0 +1,46 @@
+/*
+    Tiny BASIC interpreter written in Rust
+    Copyright (C) 2025 Artyom Makarov
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+//! Tiny BASIC interpreter.
+//!
+//! A program is loaded into a [`ProgramStorage`], either line by line or
+//! from source text with [`ProgramStorage::load`], and executed by an
+//! [`Interpreter`]. After the run, variables can be inspected through
+//! [`Interpreter::get_variable`].
+//!
+//! PRINT and INPUT go through a [`Console`]: [`Interpreter::new`] uses the
+//! standard input and output, [`Interpreter::with_console`] accepts any other
+//! implementation, e.g. the in-memory [`BufferConsole`].
+//!
+//! [`Engine`] selects how a program runs: by walking the parsed lines, or
+//! compiled to bytecode for a stack machine, or by the IL program of the
+//! design note as a reference. [`run_differential`] runs a program with the
+//! first two and compares what they print.
+
+mod tiny_basic;
+
+pub use tiny_basic::console::{BufferConsole, Console, StdConsole};
+pub use tiny_basic::engine::{run_differential, Engine, Outcome};
+pub use tiny_basic::error::{Error, ErrorKind};
+pub use tiny_basic::interpreter::Interpreter;
+pub use tiny_basic::interrupt::install_handler as install_interrupt_handler;
+pub use tiny_basic::program_storage::ProgramStorage;
+pub use tiny_basic::repl::Repl;
+pub use tiny_basic::syntax_check::{check_line, check_program, SyntaxCheck};
+pub use tiny_basic::types::{ArithmeticMode, LineIndex, Number, Value};
+pub use tiny_basic::Result;
@@ -16,14 +16,75 @@
     along with this program.  If not, see <https://www.gnu.org/licenses/>.
 */
 
-mod tiny_basic;
-use tiny_basic::repl::Repl;
+use rust_tiny_basic::{check_program, install_interrupt_handler, run_differential, ArithmeticMode, Engine, ProgramStorage, Repl, SyntaxCheck};
 
+use std::io::Read;
 use std::process::ExitCode;
 
+#[derive(Default)]
+struct Options {
+    quiet: bool,
+    repl_after: bool,
+    arithmetic: ArithmeticMode,
+    syntax_check: SyntaxCheck,
+    engine: Engine,
+    differential: bool,
+    program_path: Option<String>
+}
+
 fn main() -> ExitCode {
-    print_program_info();
-    match Repl::new().run() {
+    let options = match parse_args(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("{}", message);
+            print_usage();
+            return ExitCode::from(2);
+        }
+    };
+
+    install_interrupt_handler();
+
+    if options.differential {
+        return run_differential_mode(&options);
+    }
+
+    if !options.quiet {
+        print_program_info();
+    }
+
+    let mut repl = match &options.program_path {
+        Some(path) => {
+            let Some(program) = load_program(path, options.syntax_check) else {
+                return ExitCode::FAILURE;
+            };
+
+            let mut repl = Repl::with_program(program);
+            repl.interpreter_mut().set_arithmetic_mode(options.arithmetic);
+            repl.set_syntax_check(options.syntax_check);
+            repl.set_engine(options.engine);
+            repl.set_warning_handler(|warning| eprintln!("{}", warning));
+            if let Err(error) = repl.run_program() {
+                eprintln!("{}", error);
+                if !options.repl_after {
+                    return ExitCode::FAILURE;
+                }
+            }
+            if !options.repl_after {
+                return ExitCode::SUCCESS;
+            }
+            repl
+        },
+        None => {
+            let mut repl = Repl::new();
+            repl.interpreter_mut().set_arithmetic_mode(options.arithmetic);
+            repl.set_syntax_check(options.syntax_check);
+            repl.set_engine(options.engine);
+            repl.set_warning_handler(|warning| eprintln!("{}", warning));
+            repl
+        },
+    };
+
+    match repl.run() {
         Ok(_) => ExitCode::SUCCESS,
         Err(error) => {
             eprintln!("{}", error);
@@ -32,6 +93,105 @@ fn main() -> ExitCode {
     }
 }
 
+fn parse_args(args: impl Iterator<Item = String>) -> Result<Options, String> {
+    let mut options = Options::default();
+    for arg in args {
+        match arg.as_str() {
+            "-q" | "--quiet" => options.quiet = true,
+            "--repl-after" => options.repl_after = true,
+            _ if arg.starts_with("--arithmetic=") => {
+                options.arithmetic = arg["--arithmetic=".len()..].parse()?;
+            },
+            _ if arg.starts_with("--syntax-check=") => {
+                options.syntax_check = arg["--syntax-check=".len()..].parse()?;
+            },
+            _ if arg.starts_with("--engine=") => {
+                options.engine = arg["--engine=".len()..].parse()?;
+            },
+            "--differential" => options.differential = true,
+            _ if arg.starts_with('-') => return Err(format!("Unknown option: {}", arg)),
+            _ if options.program_path.is_some() => return Err(format!("Unexpected argument: {}", arg)),
+            _ => options.program_path = Some(arg),
+        }
+    }
+    if options.differential && options.program_path.is_none() {
+        return Err("--differential needs a program".to_string());
+    }
+    Ok(options)
+}
+
+/// Loads a program file and checks the syntax of all of its lines before anything runs.
+/// The errors are printed, and the program is only returned if it may be run.
+fn load_program(path: &str, syntax_check: SyntaxCheck) -> Option<ProgramStorage> {
+    let source = match std::fs::read_to_string(path) {
+        Ok(source) => source,
+        Err(error) => {
+            eprintln!("{}: {}", path, error);
+            return None;
+        }
+    };
+    let program = match ProgramStorage::load(&source) {
+        Ok(program) => program,
+        Err(error) => {
+            eprintln!("{}", error);
+            return None;
+        }
+    };
+
+    if syntax_check == SyntaxCheck::Off {
+        return Some(program);
+    }
+    let errors = check_program(&program);
+    for error in &errors {
+        eprintln!("{}", error);
+    }
+    if syntax_check == SyntaxCheck::Reject && !errors.is_empty() {
+        return None;
+    }
+    Some(program)
+}
+
+/// Runs the program with every engine, feeding all of the standard input to each of them
+fn run_differential_mode(options: &Options) -> ExitCode {
+    let Some(program) = options
+        .program_path
+        .as_ref()
+        .and_then(|path| load_program(path, options.syntax_check)) else {
+        return ExitCode::FAILURE;
+    };
+    let mut input = String::new();
+    if let Err(error) = std::io::stdin().read_to_string(&mut input) {
+        eprintln!("{}", error);
+        return ExitCode::FAILURE;
+    }
+
+    match run_differential(&program, &input, options.arithmetic) {
+        Ok(outcome) => {
+            print!("{}", outcome.output);
+            match outcome.error {
+                Some(error) => {
+                    eprintln!("{}", error);
+                    ExitCode::FAILURE
+                },
+                None => ExitCode::SUCCESS,
+            }
+        },
+        Err(outcomes) => {
+            eprintln!("The engines disagree");
+            for (engine, outcome) in outcomes {
+                eprintln!("--- {} output:", engine);
+                eprint!("{}", outcome.output);
+                eprintln!("--- {} error: {}", engine, outcome.error.as_deref().unwrap_or("none"));
+            }
+            ExitCode::FAILURE
+        },
+    }
+}
+
+fn print_usage() {
+    eprintln!("Usage: {} [--quiet] [--repl-after] [--arithmetic=checked|wrapping|saturating] [--syntax-check=reject|warn|off] [--engine=tree|bytecode|il] [--differential] [PROGRAM]", env!("CARGO_PKG_NAME"));
+}
+
 fn print_program_info() {
     println!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
     println!("Copyright (C) 2025 {}", env!("CARGO_PKG_AUTHORS"));
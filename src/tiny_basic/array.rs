@@ -0,0 +1,147 @@
+/*
+    Tiny BASIC interpreter written in Rust
+    Copyright (C) 2025 Artyom Makarov
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use crate::tiny_basic::error::ErrorKind;
+use crate::tiny_basic::number::Numeric;
+use crate::tiny_basic::types;
+
+/// Upper limit on the number of elements in an array, so that a typo in DIM
+/// would not exhaust the memory
+pub const MAX_ELEMENTS: usize = 1 << 16;
+
+/// Array created by DIM. As in the classic BASICs, DIM A(N) creates
+/// an array with indices in range [0; N].
+#[derive(Debug, Clone)]
+pub struct Array {
+    bounds: Vec<usize>,
+    elements: Vec<types::Value>
+}
+
+impl Array {
+    /// Creates an array with every element set to `initial`
+    pub fn new(upper_bounds: &[types::Number], initial: types::Value) -> Result<Self, ErrorKind> {
+        let mut bounds = Vec::with_capacity(upper_bounds.len());
+        let mut size: usize = 1;
+        for upper_bound in upper_bounds {
+            let bound = upper_bound
+                .to_i64()
+                .and_then(|bound| usize::try_from(bound).ok())
+                .ok_or(ErrorKind::InvalidArrayDimension)?
+                + 1;
+            size = size
+                .checked_mul(bound)
+                .filter(|size| *size <= MAX_ELEMENTS)
+                .ok_or(ErrorKind::InvalidArrayDimension)?;
+            bounds.push(bound);
+        }
+
+        Ok(Self {
+            bounds,
+            elements: vec![initial; size]
+        })
+    }
+
+    pub fn dimensions(&self) -> usize {
+        self.bounds.len()
+    }
+
+    /// Converts subscripts into the position of the element.
+    /// On failure, returns the index of the subscript which is out of range.
+    pub fn offset(&self, subscripts: &[types::Number]) -> Result<usize, usize> {
+        assert_eq!(subscripts.len(), self.dimensions(), "Number of subscripts should be checked by the caller");
+        subscripts
+            .iter()
+            .zip(&self.bounds)
+            .enumerate()
+            .try_fold(0, |offset, (i, (subscript, &bound))| {
+                subscript
+                    .to_i64()
+                    .and_then(|subscript| usize::try_from(subscript).ok())
+                    .filter(|subscript| *subscript < bound)
+                    .map(|subscript| offset * bound + subscript)
+                    .ok_or(i)
+            })
+    }
+
+    pub fn get(&self, offset: usize) -> &types::Value {
+        &self.elements[offset]
+    }
+
+    pub fn set(&mut self, offset: usize, value: types::Value) {
+        self.elements[offset] = value;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ascii::AsciiString;
+
+    use crate::tiny_basic::error::ErrorKind;
+    use crate::tiny_basic::number::Numeric;
+    use crate::tiny_basic::types::{Number, Value};
+
+    use super::{Array, MAX_ELEMENTS};
+
+    fn numbers(values: &[i64]) -> Vec<Number> {
+        values
+            .iter()
+            .map(|value| Number::from_i64(*value).unwrap())
+            .collect()
+    }
+
+    #[test]
+    #[cfg(not(any(feature = "number-f64", feature = "number-big")))]
+    fn test_offset() {
+        let array = Array::new(&[2, 3], Value::Number(0)).unwrap();
+        assert_eq!(array.offset(&[0, 0]), Ok(0));
+        assert_eq!(array.offset(&[1, 2]), Ok(6));
+        assert_eq!(array.offset(&[2, 3]), Ok(11));
+        assert_eq!(array.offset(&[3, 0]), Err(0));
+        assert_eq!(array.offset(&[0, -1]), Err(1));
+    }
+
+    #[test]
+    fn test_elements() {
+        let zero = Value::Number(Number::zero());
+        let mut array = Array::new(&numbers(&[3]), zero.clone()).unwrap();
+        assert_eq!(array.dimensions(), 1);
+        let offsets: Vec<_> = (0..=3).map(|i| array.offset(&numbers(&[i]))).collect();
+        assert_eq!(offsets, [Ok(0), Ok(1), Ok(2), Ok(3)]);
+        assert_eq!(array.offset(&numbers(&[4])), Err(0));
+
+        let four = Value::Number(Number::from_i64(4).unwrap());
+        array.set(2, four.clone());
+        assert_eq!(array.get(2), &four);
+        assert_eq!(array.get(3), &zero);
+
+        let array = Array::new(&numbers(&[1, 1]), Value::String(AsciiString::new())).unwrap();
+        assert_eq!(array.dimensions(), 2);
+        assert_eq!(array.offset(&numbers(&[1, 2])), Err(1));
+        assert_eq!(array.get(3), &Value::String(AsciiString::new()));
+    }
+
+    #[test]
+    fn test_invalid_dimensions() {
+        let zero = Value::Number(Number::zero());
+        for bounds in [&[-1][..], &[1000, 1000], &[255, 256]] {
+            assert!(matches!(Array::new(&numbers(bounds), zero.clone()), Err(ErrorKind::InvalidArrayDimension)), "{:?}", bounds);
+        }
+        let largest = Array::new(&numbers(&[255, 255]), zero).unwrap();
+        assert_eq!(largest.elements.len(), MAX_ELEMENTS);
+    }
+}
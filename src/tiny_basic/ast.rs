@@ -0,0 +1,113 @@
+/*
+    Tiny BASIC interpreter written in Rust
+    Copyright (C) 2025 Artyom Makarov
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+//! Tree of a parsed line, built by [`crate::tiny_basic::parser`].
+//!
+//! The nodes keep the locations of their text in the line, so that the errors
+//! raised while executing them point at the same place as a syntax error would.
+
+use ascii::AsciiString;
+
+use crate::tiny_basic::char_stream::{ArithmeticOperator, RelationalOperator};
+use crate::tiny_basic::types;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expression {
+    pub kind: ExpressionKind,
+    /// Where the expression starts in the line
+    pub location: usize
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExpressionKind {
+    Number(types::Number),
+    String(AsciiString),
+    Variable(AsciiString),
+    /// `name(arguments)`, either an array element or a function call.
+    /// Which one is only known when it is evaluated, once the arrays are dimensioned.
+    Call(AsciiString, Vec<Expression>),
+    /// Unary plus or minus, i.e. [`ArithmeticOperator::Add`] or [`ArithmeticOperator::Subtract`]
+    Unary(ArithmeticOperator, Box<Expression>),
+    Binary {
+        operator: ArithmeticOperator,
+        operator_location: usize,
+        lhs: Box<Expression>,
+        rhs: Box<Expression>
+    }
+}
+
+/// What LET and INPUT assign to: a variable, or an array element if there are subscripts
+#[derive(Debug, Clone, PartialEq)]
+pub struct Target {
+    pub name: AsciiString,
+    pub subscripts: Option<Vec<Expression>>,
+    pub location: usize
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArrayDeclaration {
+    pub name: AsciiString,
+    pub bounds: Vec<Expression>,
+    pub location: usize
+}
+
+/// FOR variable = start TO limit [STEP step]
+#[derive(Debug, Clone, PartialEq)]
+pub struct ForLoop {
+    pub variable: AsciiString,
+    pub variable_location: usize,
+    pub start: Expression,
+    pub limit: Expression,
+    pub step: Option<Expression>
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Statement {
+    pub kind: StatementKind,
+    /// Where the statement is over, i.e. the location of the next token.
+    /// The errors concerning the whole statement point there.
+    pub end: usize
+}
+
+/// The statements of a line are kept in a flat list, the statements executed
+/// when the condition of an IF holds being simply the ones following it
+#[derive(Debug, Clone, PartialEq)]
+pub enum StatementKind {
+    Print(Vec<Expression>),
+    /// The rest of the line is only executed if the condition holds
+    If {
+        lhs: Expression,
+        operator: RelationalOperator,
+        rhs: Expression
+    },
+    Goto(Expression),
+    Gosub(Expression),
+    Return,
+    Let(Target, Expression),
+    /// The targets together with their text, which is used as the prompt
+    Input(Vec<(Target, AsciiString)>),
+    For(ForLoop),
+    Next(Option<AsciiString>),
+    Dim(Vec<ArrayDeclaration>),
+    Randomize(Option<Expression>),
+    End,
+    /// Pauses the program, which CONT resumes
+    Stop,
+    /// REM or an apostrophe comment
+    Rem
+}
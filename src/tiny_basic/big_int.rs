@@ -0,0 +1,390 @@
+/*
+    Tiny BASIC interpreter written in Rust
+    Copyright (C) 2025 Artyom Makarov
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use std::cmp::Ordering;
+use std::fmt;
+
+/// Arbitrary-precision signed integer used by the `number-big` back-end
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct BigInt {
+    negative: bool,
+    /// Little-endian digits in base 2^32 without leading zeros, so zero has no digits
+    magnitude: Vec<u32>
+}
+
+/// The largest power of ten which fits into a digit, used for the decimal conversions
+const DECIMAL_BASE: u32 = 1_000_000_000;
+const DECIMAL_DIGITS: usize = 9;
+
+impl BigInt {
+    fn new(negative: bool, mut magnitude: Vec<u32>) -> Self {
+        while magnitude.last() == Some(&0) {
+            magnitude.pop();
+        }
+        let negative = negative && !magnitude.is_empty();
+        BigInt { negative, magnitude }
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.magnitude.is_empty()
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.negative
+    }
+
+    pub fn abs(mut self) -> Self {
+        self.negative = false;
+        self
+    }
+
+    /// Returns the quotient rounded towards zero, as the division of the primitive integers does.
+    /// The divisor must not be zero.
+    pub fn div(&self, rhs: &Self) -> Self {
+        BigInt::new(self.negative != rhs.negative, div_magnitude(&self.magnitude, &rhs.magnitude))
+    }
+
+    fn add_signed(&self, rhs: &Self, rhs_negative: bool) -> Self {
+        if self.negative == rhs_negative {
+            return BigInt::new(self.negative, add_magnitude(&self.magnitude, &rhs.magnitude));
+        }
+        match cmp_magnitude(&self.magnitude, &rhs.magnitude) {
+            Ordering::Less => BigInt::new(rhs_negative, sub_magnitude(&rhs.magnitude, &self.magnitude)),
+            _ => BigInt::new(self.negative, sub_magnitude(&self.magnitude, &rhs.magnitude)),
+        }
+    }
+}
+
+impl From<i64> for BigInt {
+    fn from(value: i64) -> Self {
+        let magnitude = value.unsigned_abs();
+        BigInt::new(value < 0, vec![magnitude as u32, (magnitude >> 32) as u32])
+    }
+}
+
+/// Error returned when a number does not fit into a primitive integer
+#[derive(Debug, PartialEq, Eq)]
+pub struct TryFromBigIntError;
+
+impl TryFrom<&BigInt> for i64 {
+    type Error = TryFromBigIntError;
+
+    fn try_from(value: &BigInt) -> Result<Self, Self::Error> {
+        if value.magnitude.len() > 2 {
+            return Err(TryFromBigIntError);
+        }
+        let magnitude = value
+            .magnitude
+            .iter()
+            .rev()
+            .fold(0u64, |magnitude, &digit| (magnitude << 32) | u64::from(digit));
+        if value.negative {
+            0i64.checked_sub_unsigned(magnitude)
+        } else {
+            i64::try_from(magnitude).ok()
+        }
+        .ok_or(TryFromBigIntError)
+    }
+}
+
+impl std::ops::Add for &BigInt {
+    type Output = BigInt;
+
+    fn add(self, rhs: Self) -> BigInt {
+        self.add_signed(rhs, rhs.negative)
+    }
+}
+
+impl std::ops::Sub for &BigInt {
+    type Output = BigInt;
+
+    fn sub(self, rhs: Self) -> BigInt {
+        self.add_signed(rhs, !rhs.negative)
+    }
+}
+
+impl std::ops::Mul for &BigInt {
+    type Output = BigInt;
+
+    fn mul(self, rhs: Self) -> BigInt {
+        BigInt::new(self.negative != rhs.negative, mul_magnitude(&self.magnitude, &rhs.magnitude))
+    }
+}
+
+impl std::ops::Neg for BigInt {
+    type Output = BigInt;
+
+    fn neg(self) -> BigInt {
+        let negative = !self.negative;
+        BigInt::new(negative, self.magnitude)
+    }
+}
+
+impl PartialOrd for BigInt {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for BigInt {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self.negative, other.negative) {
+            (false, true) => Ordering::Greater,
+            (true, false) => Ordering::Less,
+            (false, false) => cmp_magnitude(&self.magnitude, &other.magnitude),
+            (true, true) => cmp_magnitude(&other.magnitude, &self.magnitude),
+        }
+    }
+}
+
+/// Error returned when a string is not an optionally signed sequence of decimal digits
+#[derive(Debug, PartialEq, Eq)]
+pub struct ParseBigIntError;
+
+impl std::str::FromStr for BigInt {
+    type Err = ParseBigIntError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (negative, digits) = match s.as_bytes().first() {
+            Some(b'-') => (true, &s[1..]),
+            Some(b'+') => (false, &s[1..]),
+            _ => (false, s),
+        };
+        if digits.is_empty() || !digits.bytes().all(|digit| digit.is_ascii_digit()) {
+            return Err(ParseBigIntError);
+        }
+        let mut magnitude = Vec::new();
+        // The first chunk is shorter, so that the rest have exactly DECIMAL_DIGITS digits
+        let first_chunk = match digits.len() % DECIMAL_DIGITS {
+            0 => DECIMAL_DIGITS,
+            length => length,
+        };
+        let mut start = 0;
+        let mut end = first_chunk;
+        while start < digits.len() {
+            let chunk: u32 = digits[start..end].parse().expect("Chunk should consist of digits");
+            let scale = 10u32.pow((end - start) as u32);
+            mul_add_small(&mut magnitude, scale, chunk);
+            start = end;
+            end += DECIMAL_DIGITS;
+        }
+        Ok(BigInt::new(negative, magnitude))
+    }
+}
+
+impl fmt::Display for BigInt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut chunks = Vec::new();
+        let mut magnitude = self.magnitude.clone();
+        while !magnitude.is_empty() {
+            chunks.push(div_small(&mut magnitude, DECIMAL_BASE));
+        }
+        if self.negative {
+            write!(f, "-")?;
+        }
+        match chunks.pop() {
+            Some(chunk) => write!(f, "{}", chunk)?,
+            None => write!(f, "0")?,
+        }
+        for chunk in chunks.iter().rev() {
+            write!(f, "{:0width$}", chunk, width = DECIMAL_DIGITS)?;
+        }
+        Ok(())
+    }
+}
+
+fn trim(magnitude: &mut Vec<u32>) {
+    while magnitude.last() == Some(&0) {
+        magnitude.pop();
+    }
+}
+
+fn cmp_magnitude(lhs: &[u32], rhs: &[u32]) -> Ordering {
+    lhs.len()
+        .cmp(&rhs.len())
+        .then_with(|| lhs.iter().rev().cmp(rhs.iter().rev()))
+}
+
+fn add_magnitude(lhs: &[u32], rhs: &[u32]) -> Vec<u32> {
+    let (longer, shorter) = if lhs.len() >= rhs.len() { (lhs, rhs) } else { (rhs, lhs) };
+    let mut result = Vec::with_capacity(longer.len() + 1);
+    let mut carry = 0u64;
+    for (i, &digit) in longer.iter().enumerate() {
+        let sum = u64::from(digit) + u64::from(shorter.get(i).copied().unwrap_or(0)) + carry;
+        result.push(sum as u32);
+        carry = sum >> 32;
+    }
+    result.push(carry as u32);
+    trim(&mut result);
+    result
+}
+
+/// `lhs` must not be less than `rhs`
+fn sub_magnitude(lhs: &[u32], rhs: &[u32]) -> Vec<u32> {
+    let mut result = Vec::with_capacity(lhs.len());
+    let mut borrow = false;
+    for (i, &digit) in lhs.iter().enumerate() {
+        let (difference, borrow_rhs) = digit.overflowing_sub(rhs.get(i).copied().unwrap_or(0));
+        let (difference, borrow_carry) = difference.overflowing_sub(u32::from(borrow));
+        result.push(difference);
+        borrow = borrow_rhs || borrow_carry;
+    }
+    assert!(!borrow, "Minuend should not be less than subtrahend");
+    trim(&mut result);
+    result
+}
+
+fn mul_magnitude(lhs: &[u32], rhs: &[u32]) -> Vec<u32> {
+    if lhs.is_empty() || rhs.is_empty() {
+        return Vec::new();
+    }
+    let mut result = vec![0u32; lhs.len() + rhs.len()];
+    for (i, &lhs_digit) in lhs.iter().enumerate() {
+        let mut carry = 0u64;
+        for (j, &rhs_digit) in rhs.iter().enumerate() {
+            let product = u64::from(lhs_digit) * u64::from(rhs_digit) + u64::from(result[i + j]) + carry;
+            result[i + j] = product as u32;
+            carry = product >> 32;
+        }
+        result[i + rhs.len()] = carry as u32;
+    }
+    trim(&mut result);
+    result
+}
+
+/// magnitude = magnitude * factor + addend
+fn mul_add_small(magnitude: &mut Vec<u32>, factor: u32, addend: u32) {
+    let mut carry = u64::from(addend);
+    for digit in magnitude.iter_mut() {
+        let value = u64::from(*digit) * u64::from(factor) + carry;
+        *digit = value as u32;
+        carry = value >> 32;
+    }
+    if carry > 0 {
+        magnitude.push(carry as u32);
+    }
+}
+
+/// Divides the magnitude in place and returns the remainder
+fn div_small(magnitude: &mut Vec<u32>, divisor: u32) -> u32 {
+    let mut remainder = 0u64;
+    for digit in magnitude.iter_mut().rev() {
+        let value = (remainder << 32) | u64::from(*digit);
+        *digit = (value / u64::from(divisor)) as u32;
+        remainder = value % u64::from(divisor);
+    }
+    trim(magnitude);
+    remainder as u32
+}
+
+/// Long division one bit at a time. It is quadratic, but simple,
+/// and the numbers in BASIC programs are not that long.
+fn div_magnitude(lhs: &[u32], rhs: &[u32]) -> Vec<u32> {
+    assert!(!rhs.is_empty(), "Divisor should not be zero");
+    if let [divisor] = rhs {
+        let mut quotient = lhs.to_vec();
+        div_small(&mut quotient, *divisor);
+        return quotient;
+    }
+    let mut quotient = vec![0u32; lhs.len()];
+    let mut remainder: Vec<u32> = Vec::with_capacity(rhs.len() + 1);
+    for i in (0..lhs.len() * 32).rev() {
+        // remainder = remainder * 2 + bit i of lhs
+        let mut carry = (lhs[i / 32] >> (i % 32)) & 1;
+        for digit in remainder.iter_mut() {
+            let shifted_out = *digit >> 31;
+            *digit = (*digit << 1) | carry;
+            carry = shifted_out;
+        }
+        if carry > 0 {
+            remainder.push(carry);
+        }
+        if cmp_magnitude(&remainder, rhs) != Ordering::Less {
+            remainder = sub_magnitude(&remainder, rhs);
+            quotient[i / 32] |= 1 << (i % 32);
+        }
+    }
+    trim(&mut quotient);
+    quotient
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tiny_basic::random::Prng;
+
+    use super::BigInt;
+
+    fn big(text: &str) -> BigInt {
+        text.parse().unwrap()
+    }
+
+    #[test]
+    fn test_parse_and_display() {
+        for text in ["0", "7", "-7", "4294967296", "-18446744073709551617", "1000000000000000000000000000001"] {
+            assert_eq!(big(text).to_string(), text);
+        }
+        assert_eq!(big("-0").to_string(), "0");
+        assert_eq!(big("+0042").to_string(), "42");
+        assert!("".parse::<BigInt>().is_err());
+        assert!("1.5".parse::<BigInt>().is_err());
+        assert!("-".parse::<BigInt>().is_err());
+    }
+
+    #[test]
+    fn test_factorial() {
+        let factorial = (1..=30).fold(BigInt::from(1), |product, i| &product * &BigInt::from(i));
+        assert_eq!(factorial.to_string(), "265252859812191058636308480000000");
+        let quotient = (1..=30).fold(factorial, |quotient, i| quotient.div(&BigInt::from(i)));
+        assert_eq!(quotient, BigInt::from(1));
+    }
+
+    #[test]
+    fn test_conversions() {
+        for value in [0, 1, -1, i64::MAX, i64::MIN, 1 << 40] {
+            assert_eq!(i64::try_from(&BigInt::from(value)), Ok(value));
+        }
+        assert!(i64::try_from(&(&BigInt::from(i64::MAX) + &BigInt::from(1))).is_err());
+        assert!(i64::try_from(&(&BigInt::from(i64::MIN) - &BigInt::from(1))).is_err());
+    }
+
+    /// Compares the operations with i128 on the values which fit into i64
+    #[test]
+    fn test_against_i128() {
+        let prng = Prng::new(14);
+        let random = || {
+            let value = prng.next_u64() as i64;
+            // Exercise the short numbers and the single digit division as well
+            match prng.below(3) {
+                0 => value >> 40,
+                _ => value,
+            }
+        };
+        for _ in 0..5000 {
+            let (a, b) = (random(), random());
+            let (big_a, big_b) = (BigInt::from(a), BigInt::from(b));
+            let (a, b) = (i128::from(a), i128::from(b));
+            assert_eq!((&big_a + &big_b).to_string(), (a + b).to_string());
+            assert_eq!((&big_a - &big_b).to_string(), (a - b).to_string());
+            assert_eq!((&big_a * &big_b).to_string(), (a * b).to_string());
+            assert_eq!(big_a.cmp(&big_b), a.cmp(&b));
+            if b != 0 {
+                assert_eq!(big_a.div(&big_b).to_string(), (a / b).to_string());
+            }
+        }
+    }
+}
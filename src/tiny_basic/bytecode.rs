@@ -0,0 +1,398 @@
+/*
+    Tiny BASIC interpreter written in Rust
+    Copyright (C) 2025 Artyom Makarov
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+//! Compiles a whole program into instructions for the stack machine of [`crate::tiny_basic::vm`].
+//!
+//! Jumps to constant line numbers are resolved when compiling, `GOTO` and `GOSUB`
+//! with a computed line number look the line up in the table of lines. The table
+//! also gives the line of an instruction, so that runtime errors keep their line numbers.
+
+use std::collections::HashMap;
+
+use ascii::{AsciiStr, AsciiString};
+
+use crate::tiny_basic::ast::{self, ExpressionKind, StatementKind};
+use crate::tiny_basic::char_stream::{ArithmeticOperator, RelationalOperator};
+use crate::tiny_basic::error::ErrorKind;
+use crate::tiny_basic::program_storage::ProgramStorage;
+use crate::tiny_basic::types;
+
+/// Index of an instruction
+pub type Address = usize;
+/// Index of a name or a text in [`Bytecode::strings`]
+pub type StringIndex = u32;
+/// Index of the first of the locations in [`Bytecode::locations`], e.g. of the subscripts
+pub type LocationsIndex = u32;
+
+#[derive(Debug, Clone)]
+pub enum Instruction {
+    /// Pushes a value from [`Bytecode::constants`]
+    Constant(u32),
+    /// Pushes the value of a variable
+    Load(StringIndex),
+    /// Checks that the value on top of the stack, which starts at `location`, is a number
+    RequireNumber { location: usize },
+    Unary { operator: ArithmeticOperator, operand_location: usize, location: usize },
+    Binary { operator: ArithmeticOperator, operator_location: usize, rhs_location: usize },
+    /// Fails unless the name is an array or a function, before the arguments are evaluated
+    CheckCallable { name: StringIndex, location: usize },
+    /// Pops the arguments, pushes the array element or the result of the function
+    Call { name: StringIndex, arguments: u32, argument_locations: LocationsIndex, location: usize },
+    /// Pops and prints the values
+    Print(u32),
+    /// Pops two values and jumps to the next line unless the comparison holds
+    JumpUnless { operator: RelationalOperator, rhs_location: usize, target: Address },
+    Jump(Address),
+    /// Pops a line number and jumps to the line
+    JumpToLine,
+    Gosub(Address),
+    /// Pops a line number and calls the line
+    GosubLine,
+    Return,
+    /// Pops the subscripts and finds the element which is assigned to
+    ElementOffset { name: StringIndex, subscripts: u32, subscript_locations: LocationsIndex, location: usize },
+    /// Pops the value of LET
+    StoreVariable { name: StringIndex, value_location: usize },
+    /// Pops the value of LET, the element is the one found by the last [`Instruction::ElementOffset`]
+    StoreElement { name: StringIndex, value_location: usize },
+    InputVariable { name: StringIndex, prompt: StringIndex },
+    InputElement { name: StringIndex, prompt: StringIndex },
+    /// Pops the bounds
+    Dim { name: StringIndex, bounds: u32, location: usize },
+    /// Pops the step if there is one, the limit and the start
+    For { variable: StringIndex, step: bool },
+    Next { variable: Option<StringIndex>, end: usize },
+    /// Pops the seed if there is one, the location being where it starts
+    Randomize { seed_location: Option<usize> },
+    Fail { kind: ErrorKind, location: usize },
+    /// Reports the syntax error of the line, which could not be compiled
+    SyntaxError,
+    /// Pauses the program with a break
+    Stop,
+    End
+}
+
+pub struct Bytecode<'p> {
+    program: &'p ProgramStorage,
+    pub code: Vec<Instruction>,
+    pub constants: Vec<types::Value>,
+    pub strings: Vec<AsciiString>,
+    pub locations: Vec<usize>,
+    /// Every line with the address of its first instruction, sorted by the line number
+    pub lines: Vec<(types::LineIndex, Address)>,
+    /// Address of the first instruction of every statement, in ascending order
+    pub statements: Vec<Address>
+}
+
+impl<'p> Bytecode<'p> {
+    pub fn compile(program: &'p ProgramStorage) -> Self {
+        let mut compiler = Compiler {
+            program,
+            bytecode: Bytecode {
+                program,
+                code: Vec::new(),
+                constants: Vec::new(),
+                strings: Vec::new(),
+                locations: Vec::new(),
+                lines: Vec::new(),
+                statements: Vec::new()
+            },
+            string_indices: HashMap::new(),
+            line_jumps: Vec::new(),
+            next_line_jumps: Vec::new()
+        };
+        for (line, _) in program.iter() {
+            compiler.line(*line);
+        }
+        compiler.emit(Instruction::End);
+        compiler.finish()
+    }
+
+    pub fn program(&self) -> &'p ProgramStorage {
+        self.program
+    }
+
+    pub fn string(&self, index: StringIndex) -> &AsciiStr {
+        &self.strings[index as usize]
+    }
+
+    /// Location of the `i`-th of the expressions whose locations start at `index`
+    pub fn location(&self, index: LocationsIndex, i: usize) -> usize {
+        self.locations[index as usize + i]
+    }
+
+    /// Address of the first instruction of a line
+    pub fn find_line(&self, line: types::LineIndex) -> Option<Address> {
+        self.lines
+            .binary_search_by_key(&line, |(line, _)| *line)
+            .ok()
+            .map(|i| self.lines[i].1)
+    }
+
+    /// Address of the first instruction of the statement which an instruction belongs to
+    pub fn statement_start(&self, address: Address) -> Address {
+        let i = self.statements.partition_point(|start| *start <= address);
+        i.checked_sub(1).map_or(0, |i| self.statements[i])
+    }
+
+    /// Line which an instruction belongs to
+    pub fn line_of(&self, address: Address) -> Option<types::LineIndex> {
+        let i = self.lines.partition_point(|(_, start)| *start <= address);
+        i.checked_sub(1).map(|i| self.lines[i].0)
+    }
+}
+
+struct Compiler<'p> {
+    program: &'p ProgramStorage,
+    bytecode: Bytecode<'p>,
+    string_indices: HashMap<AsciiString, StringIndex>,
+    /// Jumps to constant line numbers, which are resolved once every line is compiled
+    line_jumps: Vec<(Address, types::LineIndex)>,
+    /// Jumps of the IFs of the current line, which go to the next line
+    next_line_jumps: Vec<Address>
+}
+
+impl<'p> Compiler<'p> {
+    fn emit(&mut self, instruction: Instruction) -> Address {
+        self.bytecode.code.push(instruction);
+        self.bytecode.code.len() - 1
+    }
+
+    fn constant(&mut self, value: types::Value) -> u32 {
+        self.bytecode.constants.push(value);
+        (self.bytecode.constants.len() - 1) as u32
+    }
+
+    fn locations(&mut self, expressions: &[ast::Expression]) -> LocationsIndex {
+        let index = self.bytecode.locations.len() as LocationsIndex;
+        self.bytecode.locations.extend(expressions.iter().map(|expression| expression.location));
+        index
+    }
+
+    fn string(&mut self, string: &AsciiStr) -> StringIndex {
+        if let Some(index) = self.string_indices.get(string) {
+            return *index;
+        }
+        let index = self.bytecode.strings.len() as StringIndex;
+        self.bytecode.strings.push(string.to_owned());
+        self.string_indices.insert(string.to_owned(), index);
+        index
+    }
+
+    fn line(&mut self, line: types::LineIndex) {
+        let start = self.bytecode.code.len();
+        self.bytecode.lines.push((line, start));
+        match self.program.get_statements(line) {
+            Some(Ok(statements)) => {
+                for statement in statements {
+                    self.bytecode.statements.push(self.bytecode.code.len());
+                    self.statement(statement);
+                }
+            },
+            _ => {
+                self.bytecode.statements.push(start);
+                self.emit(Instruction::SyntaxError);
+            },
+        }
+
+        let next_line = self.bytecode.code.len();
+        for address in std::mem::take(&mut self.next_line_jumps) {
+            if let Instruction::JumpUnless { target, .. } = &mut self.bytecode.code[address] {
+                *target = next_line;
+            }
+        }
+    }
+
+    fn finish(mut self) -> Bytecode<'p> {
+        for (address, line) in std::mem::take(&mut self.line_jumps) {
+            let target = self.bytecode.find_line(line).expect("Jump target should have been checked");
+            match &mut self.bytecode.code[address] {
+                Instruction::Jump(address) | Instruction::Gosub(address) => *address = target,
+                instruction => unreachable!("Not a jump: {:?}", instruction),
+            }
+        }
+        self.bytecode
+    }
+
+    /// Line number of GOTO or GOSUB if it is a constant of an existing line.
+    /// Other line numbers are looked up when the jump is executed, which also reports the bad ones.
+    fn constant_line(&self, expression: &ast::Expression) -> Option<types::LineIndex> {
+        match &expression.kind {
+            ExpressionKind::Number(number) => types::LineIndex::from_number(number.to_owned())
+                .ok()
+                .filter(|line| self.program.get_line(*line).is_some()),
+            _ => None,
+        }
+    }
+
+    fn statement(&mut self, statement: &ast::Statement) {
+        match &statement.kind {
+            StatementKind::Print(expressions) => {
+                for expression in expressions {
+                    self.expression(expression);
+                }
+                self.emit(Instruction::Print(expressions.len() as u32));
+            },
+            StatementKind::If { lhs, operator, rhs } => {
+                self.expression(lhs);
+                self.expression(rhs);
+                let address = self.emit(Instruction::JumpUnless { operator: *operator, rhs_location: rhs.location, target: 0 });
+                self.next_line_jumps.push(address);
+            },
+            StatementKind::Goto(line) => match self.constant_line(line) {
+                Some(line) => {
+                    let address = self.emit(Instruction::Jump(0));
+                    self.line_jumps.push((address, line));
+                },
+                None => {
+                    self.numeric_expression(line);
+                    self.emit(Instruction::JumpToLine);
+                },
+            },
+            StatementKind::Gosub(line) => match self.constant_line(line) {
+                Some(line) => {
+                    let address = self.emit(Instruction::Gosub(0));
+                    self.line_jumps.push((address, line));
+                },
+                None => {
+                    self.numeric_expression(line);
+                    self.emit(Instruction::GosubLine);
+                },
+            },
+            StatementKind::Return => {
+                self.emit(Instruction::Return);
+            },
+            StatementKind::Let(target, value) => {
+                let name = self.string(&target.name);
+                match &target.subscripts {
+                    Some(subscripts) => {
+                        self.element_offset(target, subscripts);
+                        self.expression(value);
+                        self.emit(Instruction::StoreElement { name, value_location: value.location });
+                    },
+                    None => {
+                        self.expression(value);
+                        self.emit(Instruction::StoreVariable { name, value_location: value.location });
+                    },
+                }
+            },
+            StatementKind::Input(targets) => {
+                for (target, text) in targets {
+                    let name = self.string(&target.name);
+                    let prompt = self.string(text);
+                    match &target.subscripts {
+                        Some(subscripts) => {
+                            self.element_offset(target, subscripts);
+                            self.emit(Instruction::InputElement { name, prompt });
+                        },
+                        None => {
+                            self.emit(Instruction::InputVariable { name, prompt });
+                        },
+                    }
+                }
+            },
+            StatementKind::For(for_loop) => {
+                if types::Value::is_string_name(&for_loop.variable) {
+                    self.emit(Instruction::Fail { kind: ErrorKind::TypeMismatch, location: for_loop.variable_location });
+                }
+                self.numeric_expression(&for_loop.start);
+                self.numeric_expression(&for_loop.limit);
+                if let Some(step) = &for_loop.step {
+                    self.numeric_expression(step);
+                }
+                let variable = self.string(&for_loop.variable);
+                self.emit(Instruction::For { variable, step: for_loop.step.is_some() });
+            },
+            StatementKind::Next(variable) => {
+                let variable = variable.as_deref().map(|variable| self.string(variable));
+                self.emit(Instruction::Next { variable, end: statement.end });
+            },
+            StatementKind::Dim(arrays) => {
+                for declaration in arrays {
+                    for bound in &declaration.bounds {
+                        self.numeric_expression(bound);
+                    }
+                    let name = self.string(&declaration.name);
+                    self.emit(Instruction::Dim { name, bounds: declaration.bounds.len() as u32, location: declaration.location });
+                }
+            },
+            StatementKind::Randomize(seed) => {
+                if let Some(seed) = seed {
+                    self.numeric_expression(seed);
+                }
+                self.emit(Instruction::Randomize { seed_location: seed.as_ref().map(|seed| seed.location) });
+            },
+            StatementKind::End => {
+                self.emit(Instruction::End);
+            },
+            StatementKind::Stop => {
+                self.emit(Instruction::Stop);
+            },
+            StatementKind::Rem => (),
+        }
+    }
+
+    fn element_offset(&mut self, target: &ast::Target, subscripts: &[ast::Expression]) {
+        for subscript in subscripts {
+            self.numeric_expression(subscript);
+        }
+        let name = self.string(&target.name);
+        let subscript_locations = self.locations(subscripts);
+        self.emit(Instruction::ElementOffset { name, subscripts: subscripts.len() as u32, subscript_locations, location: target.location });
+    }
+
+    fn numeric_expression(&mut self, expression: &ast::Expression) {
+        self.expression(expression);
+        self.emit(Instruction::RequireNumber { location: expression.location });
+    }
+
+    fn expression(&mut self, expression: &ast::Expression) {
+        match &expression.kind {
+            ExpressionKind::Number(number) => {
+                let index = self.constant(number.to_owned().into());
+                self.emit(Instruction::Constant(index));
+            },
+            ExpressionKind::String(string) => {
+                let index = self.constant(types::Value::String(string.clone()));
+                self.emit(Instruction::Constant(index));
+            },
+            ExpressionKind::Variable(name) => {
+                let name = self.string(name);
+                self.emit(Instruction::Load(name));
+            },
+            ExpressionKind::Call(name, arguments) => {
+                let name = self.string(name);
+                self.emit(Instruction::CheckCallable { name, location: expression.location });
+                for argument in arguments {
+                    self.numeric_expression(argument);
+                }
+                let argument_locations = self.locations(arguments);
+                self.emit(Instruction::Call { name, arguments: arguments.len() as u32, argument_locations, location: expression.location });
+            },
+            ExpressionKind::Unary(operator, operand) => {
+                self.expression(operand);
+                self.emit(Instruction::Unary { operator: *operator, operand_location: operand.location, location: expression.location });
+            },
+            ExpressionKind::Binary { operator, operator_location, lhs, rhs } => {
+                self.expression(lhs);
+                self.expression(rhs);
+                self.emit(Instruction::Binary { operator: *operator, operator_location: *operator_location, rhs_location: rhs.location });
+            },
+        }
+    }
+}
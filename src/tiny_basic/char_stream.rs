@@ -20,9 +20,11 @@
 use ascii::{AsAsciiStr, AsciiChar, AsciiStr};
 
 use crate::tiny_basic;
-use crate::tiny_basic::error::{Error, ErrorKind};
+use crate::tiny_basic::error::{Error, ErrorContext, ErrorKind};
 
-#[derive(Debug, PartialEq, Eq)]
+const REM: &str = "REM";
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub enum Keyword {
     Print,
     If,
@@ -35,7 +37,20 @@ pub enum Keyword {
     Gosub,
     Return,
     End,
-    Input
+    Input,
+    Save,
+    Load,
+    For,
+    To,
+    Step,
+    Next,
+    Rem,
+    Dim,
+    Randomize,
+    Check,
+    Stop,
+    Cont,
+    New
 }
 
 pub enum Statement {
@@ -46,17 +61,91 @@ pub enum Statement {
     If,
     Gosub,
     Return,
-    Input
+    Input,
+    For,
+    Next,
+    Rem,
+    Dim,
+    Randomize,
+    Stop
 }
 
 pub enum Command {
     Run,
     List,
     Clear,
+    Save,
+    Load,
+    Check,
+    Cont,
+    New,
 }
 
-#[derive(Debug, PartialEq, Eq)]
+impl Keyword {
+    /// The statement which starts with the keyword, if any
+    pub fn statement(&self) -> Option<Statement> {
+        match self {
+            Keyword::Print => Some(Statement::Print),
+            Keyword::If => Some(Statement::If),
+            Keyword::Then => None,
+            Keyword::Run => None,
+            Keyword::List => None,
+            Keyword::Clear => None,
+            Keyword::Goto => Some(Statement::Goto),
+            Keyword::Let => Some(Statement::Let),
+            Keyword::Gosub => Some(Statement::Gosub),
+            Keyword::Return => Some(Statement::Return),
+            Keyword::End => Some(Statement::End),
+            Keyword::Input => Some(Statement::Input),
+            Keyword::Save => None,
+            Keyword::Load => None,
+            Keyword::For => Some(Statement::For),
+            Keyword::To => None,
+            Keyword::Step => None,
+            Keyword::Next => Some(Statement::Next),
+            Keyword::Rem => Some(Statement::Rem),
+            Keyword::Dim => Some(Statement::Dim),
+            Keyword::Randomize => Some(Statement::Randomize),
+            Keyword::Check => None,
+            Keyword::Stop => Some(Statement::Stop),
+            Keyword::Cont => None,
+            Keyword::New => None,
+        }
+    }
 
+    /// The command which starts with the keyword, if any
+    pub fn command(&self) -> Option<Command> {
+        match self {
+            Keyword::Print => None,
+            Keyword::If => None,
+            Keyword::Then => None,
+            Keyword::Run => Some(Command::Run),
+            Keyword::List => Some(Command::List),
+            Keyword::Clear => Some(Command::Clear),
+            Keyword::Goto => None,
+            Keyword::Let => None,
+            Keyword::Gosub => None,
+            Keyword::Return => None,
+            Keyword::End => None,
+            Keyword::Input => None,
+            Keyword::Save => Some(Command::Save),
+            Keyword::Load => Some(Command::Load),
+            Keyword::For => None,
+            Keyword::To => None,
+            Keyword::Step => None,
+            Keyword::Next => None,
+            Keyword::Rem => None,
+            Keyword::Dim => None,
+            Keyword::Randomize => None,
+            Keyword::Check => Some(Command::Check),
+            Keyword::Stop => None,
+            Keyword::Cont => Some(Command::Cont),
+            Keyword::New => Some(Command::New),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub enum RelationalOperator {
     Less,
     Greater,
@@ -66,6 +155,24 @@ pub enum RelationalOperator {
     Equal
 }
 
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ArithmeticOperator {
+    Add,
+    Subtract,
+    Multiply,
+    Divide
+}
+
+impl ArithmeticOperator {
+    /// Operators with higher precedence bind tighter
+    pub fn precedence(&self) -> u8 {
+        match self {
+            ArithmeticOperator::Add | ArithmeticOperator::Subtract => 1,
+            ArithmeticOperator::Multiply | ArithmeticOperator::Divide => 2,
+        }
+    }
+}
+
 #[derive(Default, Clone, PartialEq, Copy)]
 struct StreamState {
     cur: usize
@@ -94,10 +201,6 @@ impl<'a> AsciiCharStream<'a> {
         }
     }
 
-    pub fn get_stream(&self) -> &'a AsciiStr {
-        self.stream
-    }
-
     pub fn get_location(&self) -> usize {
         self.state.cur
     }
@@ -106,7 +209,7 @@ impl<'a> AsciiCharStream<'a> {
         self.stream.get_ascii(self.state.cur)
     }
 
-    pub fn match_char<F>(&mut self, predicate: F) -> Option<AsciiChar>
+    pub fn match_char<F>(&self, predicate: F) -> Option<AsciiChar>
     where F: Fn(&AsciiChar) -> bool {
         match self.peek() {
             Some(ch) => if predicate(&ch) {
@@ -135,29 +238,37 @@ impl<'a> AsciiCharStream<'a> {
         .and(Some(()))
     }
 
-    pub fn consume_number(&mut self) -> Option<&AsciiStr> {
-        let mut number_end = self.clone();
-        while number_end.match_char(AsciiChar::is_ascii_digit).is_some() {
-            number_end.advance();
-        }
+    /// Consumes digits, optionally followed by a decimal point and a fractional part.
+    /// Whether the fraction is valid depends on the numeric back-end.
+    pub fn consume_number(&mut self) -> Option<&'a AsciiStr> {
+        let mut number_end = *self;
+        number_end.advance_while(AsciiChar::is_ascii_digit);
         if number_end.state == self.state {
             None
         } else {
+            if number_end.match_char(|ch| *ch == AsciiChar::Dot).is_some() {
+                number_end.advance();
+                number_end.advance_while(AsciiChar::is_ascii_digit);
+            }
             let number_str = &self.stream[self.state.cur..number_end.state.cur];
-            *self = number_end.clone();
+            *self = number_end;
             self.trim_start();
             Some(number_str)
         }
     }
 
     pub fn consume_keyword(&mut self) -> Option<Keyword> {
-        let mut keyword_end = self.clone();
+        let mut keyword_end = *self;
         keyword_end.advance_while(AsciiChar::is_ascii_alphabetic);
         if keyword_end.state == self.state {
             None
         } else {
             let keyword = &self.stream[self.state.cur..keyword_end.state.cur];
-            *self = keyword_end.clone();
+            // REM must be a whole word, REM1 or REM_A are variables like REMARK
+            if keyword.as_str() == REM && keyword_end.match_char(|ch| ch.is_ascii_digit() || *ch == AsciiChar::UnderScore || *ch == AsciiChar::Dollar).is_some() {
+                return None;
+            }
+            *self = keyword_end;
             self.trim_start();
             match keyword.as_str() {
                 "PRINT" => Some(Keyword::Print),
@@ -172,51 +283,32 @@ impl<'a> AsciiCharStream<'a> {
                 "RETURN" => Some(Keyword::Return),
                 "END" => Some(Keyword::End),
                 "INPUT" => Some(Keyword::Input),
+                "SAVE" => Some(Keyword::Save),
+                "LOAD" => Some(Keyword::Load),
+                "FOR" => Some(Keyword::For),
+                "TO" => Some(Keyword::To),
+                "STEP" => Some(Keyword::Step),
+                "NEXT" => Some(Keyword::Next),
+                REM => Some(Keyword::Rem),
+                "DIM" => Some(Keyword::Dim),
+                "RANDOMIZE" => Some(Keyword::Randomize),
+                "CHECK" => Some(Keyword::Check),
+                "STOP" => Some(Keyword::Stop),
+                "CONT" => Some(Keyword::Cont),
+                "NEW" => Some(Keyword::New),
                 _ => None
             }
         }
     }
 
-    pub fn consume_statement(&mut self) -> Option<Statement> {
-        match self.consume_keyword()? {
-            Keyword::Print => Some(Statement::Print),
-            Keyword::If => Some(Statement::If),
-            Keyword::Then => None,
-            Keyword::Run => None,
-            Keyword::List => None,
-            Keyword::Clear => None,
-            Keyword::Goto => Some(Statement::Goto),
-            Keyword::Let => Some(Statement::Let),
-            Keyword::Gosub => Some(Statement::Gosub),
-            Keyword::Return => Some(Statement::Return),
-            Keyword::End => Some(Statement::End),
-            Keyword::Input => Some(Statement::Input),
-        }
-    }
-
-    pub fn consume_command(&mut self) -> Option<Command> {
-        match self.consume_keyword()? {
-            Keyword::Print => None,
-            Keyword::If => None,
-            Keyword::Then => None,
-            Keyword::Run => Some(Command::Run),
-            Keyword::List => Some(Command::List),
-            Keyword::Clear => Some(Command::Clear),
-            Keyword::Goto => None,
-            Keyword::Let => None,
-            Keyword::Gosub => None,
-            Keyword::Return => None,
-            Keyword::End => None,
-            Keyword::Input => None,
-        }
-    }
-
     pub fn consume_string(&mut self) -> tiny_basic::Result<'a, Option<&'a AsciiStr>> {
-        if self.consume_char(AsciiChar::Quotation).is_none() {
+        // The opening quote is not followed by trimming, leading spaces belong to the string
+        if self.match_char(|ch| *ch == AsciiChar::Quotation).is_none() {
             return Ok(None);
         }
+        self.advance();
 
-        let mut string_end = self.clone();
+        let mut string_end = *self;
         string_end.advance_while(|ch| {
             ch.is_ascii_printable()
             && *ch != '"'
@@ -226,13 +318,15 @@ impl<'a> AsciiCharStream<'a> {
         string_end
             .consume_char(AsciiChar::Quotation)
             .ok_or(Error::from_context(&string_end, ErrorKind::Expected('"'), None))?;
-        *self = string_end.clone();
+        *self = string_end;
         self.trim_start();
         Ok(Some(string))
     }
 
-    pub fn consume_var(&mut self) -> Option<&AsciiStr> {
-        let mut var_end = self.clone();
+    /// Consumes an identifier: a letter followed by letters, digits or underscores.
+    /// The names of string variables end with `$`.
+    pub fn consume_var(&mut self) -> Option<&'a AsciiStr> {
+        let mut var_end = *self;
         var_end.advance_while(AsciiChar::is_ascii_alphabetic);
         if var_end.state == self.state {
             return None;
@@ -240,19 +334,41 @@ impl<'a> AsciiCharStream<'a> {
         var_end.advance_while(|ch| 
             ch.is_ascii_alphabetic()
             || ch.is_ascii_digit()
-            || *ch == AsciiChar::UnderScore
-            || *ch == AsciiChar::Minus);
+            || *ch == AsciiChar::UnderScore);
+        if var_end.match_char(|ch| *ch == AsciiChar::Dollar).is_some() {
+            var_end.advance();
+        }
 
         if var_end.state == self.state {
             None
         } else {
             let var_name = &self.stream[self.state.cur..var_end.state.cur];
-            *self = var_end.clone();
+            *self = var_end;
             self.trim_start();
             Some(var_name)
         }
     }
 
+    /// Consumes an apostrophe comment, which lasts until the end of the line
+    pub fn consume_comment(&mut self) -> Option<&'a AsciiStr> {
+        self.match_char(|ch| *ch == AsciiChar::Apostrophe)?;
+        self.advance();
+        Some(self.flush())
+    }
+
+    pub fn consume_arithmetic_operator(&mut self) -> Option<ArithmeticOperator> {
+        let operator = match self.peek()? {
+            AsciiChar::Plus => ArithmeticOperator::Add,
+            AsciiChar::Minus => ArithmeticOperator::Subtract,
+            AsciiChar::Asterisk => ArithmeticOperator::Multiply,
+            AsciiChar::Slash => ArithmeticOperator::Divide,
+            _ => return None,
+        };
+        self.advance();
+        self.trim_start();
+        Some(operator)
+    }
+
     pub fn consume_relop(&mut self) -> Option<RelationalOperator> {
         if self.consume_char(AsciiChar::LessThan).is_some() {
             if self.consume_char(AsciiChar::Equal).is_some() {
@@ -294,14 +410,24 @@ impl<'a> AsciiCharStream<'a> {
         }
     }
 
-    fn trim_start(&mut self) {
+    pub fn trim_start(&mut self) {
         while self.match_char(AsciiChar::is_ascii_whitespace).is_some()  {
             self.advance();
         }
     }
 
     fn advance(&mut self) {
-        self.state = self.state.clone().advance();
+        self.state = self.state.advance();
+    }
+}
+
+impl<'a> ErrorContext<'a> for AsciiCharStream<'a> {
+    fn get_stream(&self) -> &'a AsciiStr {
+        self.stream
+    }
+
+    fn get_location(&self) -> usize {
+        self.state.cur
     }
 }
 
@@ -345,6 +471,12 @@ mod tests {
             assert_eq!(stream.consume_keyword().unwrap(), Keyword::Print);
             assert_eq!(stream.consume_string().unwrap().unwrap().as_str(), "");
         }
+
+        {
+            let mut stream = AsciiCharStream::from_ascii_str(ascii::AsciiStr::from_ascii(b"\"  padded \" ").unwrap());
+            assert_eq!(stream.consume_string().unwrap().unwrap().as_str(), "  padded ");
+            assert!(stream.is_empty());
+        }
     }
 
     #[test]
@@ -354,6 +486,54 @@ mod tests {
             assert_eq!(stream.consume_keyword().unwrap(), Keyword::Print);
             assert_eq!(stream.consume_var().unwrap().as_str(), "A");
         }
+
+        {
+            let mut stream = AsciiCharStream::from_ascii_str(ascii::AsciiStr::from_ascii(b"A_1-B").unwrap());
+            assert_eq!(stream.consume_var().unwrap().as_str(), "A_1");
+            assert!(stream.consume_arithmetic_operator().is_some());
+            assert_eq!(stream.consume_var().unwrap().as_str(), "B");
+        }
+    }
+
+    #[test]
+    fn test_consume_rem() {
+        {
+            let mut stream = AsciiCharStream::from_ascii_str(ascii::AsciiStr::from_ascii(b"REM ARKABLE").unwrap());
+            assert_eq!(stream.consume_keyword().unwrap(), Keyword::Rem);
+            assert_eq!(stream.flush().as_str(), "ARKABLE");
+        }
+
+        for name in ["REMARKABLE", "REM1", "REM_A", "REM$"] {
+            let mut stream = AsciiCharStream::from_ascii_str(ascii::AsciiStr::from_ascii(name).unwrap());
+            assert!(stream.consume_keyword().is_none(), "{}", name);
+        }
+
+        {
+            let mut stream = AsciiCharStream::from_ascii_str(ascii::AsciiStr::from_ascii(b"RE").unwrap());
+            assert!(stream.consume_keyword().is_none());
+        }
+    }
+
+    #[test]
+    fn test_consume_comment() {
+        {
+            let mut stream = AsciiCharStream::from_ascii_str(ascii::AsciiStr::from_ascii(b"PRINT A ' A is \"the\" answer").unwrap());
+            assert_eq!(stream.consume_keyword().unwrap(), Keyword::Print);
+            assert!(stream.consume_comment().is_none());
+            assert_eq!(stream.consume_var().unwrap().as_str(), "A");
+            assert_eq!(stream.consume_comment().unwrap().as_str(), " A is \"the\" answer");
+            assert!(stream.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_consume_string_var() {
+        {
+            let mut stream = AsciiCharStream::from_ascii_str(ascii::AsciiStr::from_ascii(b"A$ + B").unwrap());
+            assert_eq!(stream.consume_var().unwrap().as_str(), "A$");
+            assert!(stream.consume_char(ascii::AsciiChar::Plus).is_some());
+            assert_eq!(stream.consume_var().unwrap().as_str(), "B");
+        }
     }
 
     #[test]
@@ -35,7 +35,7 @@ impl<'a> TryFrom<&'a AsciiStr> for Line<'a> {
         if let Some(line_index) = char_stream.consume_number() {
             let line_index: types::LineIndex = line_index
                 .as_str()
-                .parse::<types::Number>()?
+                .parse::<i16>()?
                 .try_into()?;
 
             Ok(Self{
@@ -0,0 +1,100 @@
+/*
+    Tiny BASIC interpreter written in Rust
+    Copyright (C) 2025 Artyom Makarov
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use std::collections::VecDeque;
+use std::io::{stdin, stdout, Write};
+
+/// The device used by PRINT and INPUT
+pub trait Console {
+    fn write(&mut self, text: &str) -> std::io::Result<()>;
+
+    /// Reads one line without the trailing newline, `None` means end of input
+    fn read_line(&mut self) -> std::io::Result<Option<String>>;
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Console backed by the standard input and output of the process
+#[derive(Default)]
+pub struct StdConsole;
+
+impl Console for StdConsole {
+    fn write(&mut self, text: &str) -> std::io::Result<()> {
+        stdout().write_all(text.as_bytes())
+    }
+
+    fn read_line(&mut self) -> std::io::Result<Option<String>> {
+        let mut line = String::new();
+        let bytes_read = stdin().read_line(&mut line)?;
+        if bytes_read == 0 {
+            Ok(None)
+        } else {
+            Ok(Some(line.trim_end_matches(['\r', '\n']).to_owned()))
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        stdout().flush()
+    }
+}
+
+/// In-memory console: input is taken from a prepared list of lines,
+/// output is collected into a string
+#[derive(Default)]
+pub struct BufferConsole {
+    input: VecDeque<String>,
+    output: String
+}
+
+impl BufferConsole {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_input(input: &str) -> Self {
+        Self {
+            input: input.lines().map(str::to_owned).collect(),
+            output: String::new()
+        }
+    }
+
+    pub fn push_input(&mut self, line: &str) {
+        self.input.push_back(line.to_owned());
+    }
+
+    pub fn output(&self) -> &str {
+        &self.output
+    }
+
+    pub fn take_output(&mut self) -> String {
+        std::mem::take(&mut self.output)
+    }
+}
+
+impl Console for BufferConsole {
+    fn write(&mut self, text: &str) -> std::io::Result<()> {
+        self.output.push_str(text);
+        Ok(())
+    }
+
+    fn read_line(&mut self) -> std::io::Result<Option<String>> {
+        Ok(self.input.pop_front())
+    }
+}
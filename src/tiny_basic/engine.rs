@@ -0,0 +1,257 @@
+/*
+    Tiny BASIC interpreter written in Rust
+    Copyright (C) 2025 Artyom Makarov
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+//! Choice of the engine which runs a program, and the differential mode running all of them.
+
+use crate::tiny_basic;
+use crate::tiny_basic::bytecode::Bytecode;
+use crate::tiny_basic::console::{BufferConsole, Console};
+use crate::tiny_basic::error::{Error as TinyBasicError, ErrorKind as TinyBasicErrorKind};
+use crate::tiny_basic::interpreter::Interpreter;
+use crate::tiny_basic::program_storage::ProgramStorage;
+use crate::tiny_basic::types;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum Engine {
+    /// Walks the trees of the lines
+    #[default]
+    Tree,
+    /// Compiles the program and runs it on a stack machine
+    Bytecode,
+    /// Runs the IL program of the design note, which only accepts the language of the note
+    Il
+}
+
+impl Engine {
+    /// The engines which run the whole language, so they must agree on any program
+    pub const ALL: [Engine; 2] = [Engine::Tree, Engine::Bytecode];
+
+    /// Runs the program from its first line, after clearing the variables and the stacks
+    pub fn run<'p, C: Console>(self, interpreter: &mut Interpreter<C>, program: &'p ProgramStorage) -> tiny_basic::Result<'p, ()> {
+        match self {
+            Engine::Tree => interpreter.run(program),
+            Engine::Bytecode => interpreter.run_bytecode(&Bytecode::compile(program)),
+            Engine::Il => interpreter.run_il(program),
+        }
+    }
+
+    /// Runs the program from a line, the variables and the stacks being cleared first as for [`Engine::run`]
+    pub fn run_from<'p, C: Console>(
+        self,
+        interpreter: &mut Interpreter<C>,
+        program: &'p ProgramStorage,
+        line: types::LineIndex
+    ) -> tiny_basic::Result<'p, ()> {
+        match self {
+            Engine::Tree => interpreter.run_from(program, line),
+            Engine::Bytecode => interpreter.run_bytecode_from(&Bytecode::compile(program), line),
+            Engine::Il => interpreter.run_il_from(program, line),
+        }
+    }
+
+    /// Resumes the program where STOP, END, a break or an error paused it
+    pub fn cont<'p, C: Console>(self, interpreter: &mut Interpreter<C>, program: &'p ProgramStorage) -> tiny_basic::Result<'p, ()> {
+        match self {
+            // The program may have been started by GOTO at the prompt on the tree walker,
+            // so it resumes on the engine which paused it
+            Engine::Tree | Engine::Bytecode => interpreter.cont(program),
+            // The design note has neither STOP nor CONT
+            Engine::Il => Err(TinyBasicError::from(TinyBasicErrorKind::CantContinue)),
+        }
+    }
+}
+
+impl std::str::FromStr for Engine {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "tree" => Ok(Engine::Tree),
+            "bytecode" => Ok(Engine::Bytecode),
+            "il" => Ok(Engine::Il),
+            _ => Err(format!("Unknown engine: {}", s)),
+        }
+    }
+}
+
+impl std::fmt::Display for Engine {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Engine::Tree => write!(f, "tree"),
+            Engine::Bytecode => write!(f, "bytecode"),
+            Engine::Il => write!(f, "il"),
+        }
+    }
+}
+
+/// What a program printed, and the error it stopped with
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Outcome {
+    pub output: String,
+    pub error: Option<String>
+}
+
+/// Runs a program on a fresh interpreter, reading the input from `input`
+pub fn run_captured(engine: Engine, program: &ProgramStorage, input: &str, arithmetic: types::ArithmeticMode) -> Outcome {
+    let mut interpreter = Interpreter::with_console(BufferConsole::with_input(input));
+    interpreter.set_arithmetic_mode(arithmetic);
+    let error = engine
+        .run(&mut interpreter, program)
+        .err()
+        .map(|error| error.to_string());
+    Outcome {
+        output: interpreter.console_mut().take_output(),
+        error
+    }
+}
+
+/// Runs a program with every engine. The outcome is returned if all of them agree,
+/// otherwise the outcome of each engine is.
+pub fn run_differential(
+    program: &ProgramStorage,
+    input: &str,
+    arithmetic: types::ArithmeticMode
+) -> Result<Outcome, Vec<(Engine, Outcome)>> {
+    let outcomes: Vec<_> = Engine::ALL
+        .iter()
+        .map(|engine| (*engine, run_captured(*engine, program, input, arithmetic)))
+        .collect();
+    if outcomes.iter().all(|(_, outcome)| *outcome == outcomes[0].1) {
+        Ok(outcomes[0].1.clone())
+    } else {
+        Err(outcomes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ascii::AsciiStr;
+
+    use crate::tiny_basic::console::BufferConsole;
+    use crate::tiny_basic::error::ErrorKind;
+    use crate::tiny_basic::interpreter::Interpreter;
+    use crate::tiny_basic::program_storage::ProgramStorage;
+    use crate::tiny_basic::types::LineIndex;
+
+    use super::Engine;
+
+    /// Runs the program, then resumes it with CONT until it can't continue,
+    /// returning the output and the error of each step
+    fn run_and_continue(engine: Engine, source: &str) -> Vec<(String, Option<ErrorKind>)> {
+        let program = ProgramStorage::load(source).unwrap();
+        let mut interpreter = Interpreter::with_console(BufferConsole::new());
+        let mut steps = Vec::new();
+        let mut result = engine.run(&mut interpreter, &program);
+        loop {
+            let error = result.err().map(|error| error.get_kind());
+            steps.push((interpreter.console_mut().take_output(), error.clone()));
+            if matches!(error, Some(ErrorKind::CantContinue)) || steps.len() > 10 {
+                return steps;
+            }
+            result = engine.cont(&mut interpreter, &program);
+        }
+    }
+
+    #[test]
+    fn test_stop_and_cont() {
+        for engine in Engine::ALL {
+            let steps = run_and_continue(engine, "5 GOTO 10\n6 STOP\n7 RETURN\n10 FOR I = 1 TO 2 : PRINT I : STOP : NEXT I\n20 GOSUB 6 : PRINT 20\n30 END\n40 PRINT 40");
+            let outputs: Vec<_> = steps.iter().map(|(output, _)| output.as_str()).collect();
+            assert_eq!(outputs, ["1 \n", "2 \n", "", "20 \n", "40 \n", ""], "{}", engine);
+            assert!(matches!(steps[0].1, Some(ErrorKind::Break)));
+            assert!(matches!(steps[2].1, Some(ErrorKind::Break)));
+            assert!(steps[3].1.is_none());
+            assert!(steps[4].1.is_none());
+            assert!(matches!(steps[5].1, Some(ErrorKind::CantContinue)));
+        }
+    }
+
+    #[test]
+    fn test_cont_after_error() {
+        for engine in Engine::ALL {
+            let program = ProgramStorage::load("10 LET A = 1\n20 PRINT A : PRINT 10 / B : PRINT 3").unwrap();
+            let mut interpreter = Interpreter::with_console(BufferConsole::new());
+            let error = engine.run(&mut interpreter, &program).err().unwrap();
+            assert!(matches!(error.get_kind(), ErrorKind::DivisionByZero));
+
+            // The failed statement is executed again
+            interpreter.execute(AsciiStr::from_ascii("LET B = 5").unwrap(), &program).unwrap();
+            engine.cont(&mut interpreter, &program).unwrap();
+            assert_eq!(interpreter.console_mut().take_output(), "1 \n2 \n3 \n", "{}", engine);
+        }
+    }
+
+    #[test]
+    fn test_cont_after_immediate_goto() {
+        for engine in Engine::ALL {
+            let program = ProgramStorage::load("10 PRINT 1\n20 STOP\n30 PRINT 3").unwrap();
+            let mut interpreter = Interpreter::with_console(BufferConsole::new());
+            let error = interpreter.execute(AsciiStr::from_ascii("GOTO 10").unwrap(), &program).err().unwrap();
+            assert!(matches!(error.get_kind(), ErrorKind::Break));
+            engine.cont(&mut interpreter, &program).unwrap();
+            assert_eq!(interpreter.console_mut().take_output(), "1 \n3 \n", "{}", engine);
+        }
+    }
+
+    #[test]
+    fn test_cont_refuses_edited_program() {
+        for engine in Engine::ALL {
+            let mut program = ProgramStorage::load("10 STOP\n20 PRINT 20").unwrap();
+            let mut interpreter = Interpreter::with_console(BufferConsole::new());
+            assert!(engine.run(&mut interpreter, &program).is_err());
+            program.insert_line(LineIndex::try_from(30).unwrap(), AsciiStr::from_ascii("PRINT 30").unwrap());
+            let error = engine.cont(&mut interpreter, &program).err().unwrap();
+            assert!(matches!(error.get_kind(), ErrorKind::CantContinue), "{}", engine);
+
+            // A program loaded anew is not mistaken for the stopped one either
+            let program = ProgramStorage::load("10 STOP\n20 PRINT 20").unwrap();
+            assert!(engine.run(&mut interpreter, &program).is_err());
+            let reloaded = ProgramStorage::load("10 STOP\n20 PRINT 20").unwrap();
+            assert!(engine.cont(&mut interpreter, &reloaded).is_err());
+            engine.cont(&mut interpreter, &program).unwrap();
+            assert_eq!(interpreter.console_mut().take_output(), "20 \n");
+        }
+    }
+
+    #[test]
+    fn test_run_clears_state() {
+        for engine in Engine::ALL {
+            let program = ProgramStorage::load("10 PRINT A\n20 LET A = 5\n30 GOSUB 100\n40 END\n100 STOP\n110 RETURN\n200 RETURN").unwrap();
+            let mut interpreter = Interpreter::with_console(BufferConsole::new());
+            let line = |line| LineIndex::try_from(line).unwrap();
+
+            assert!(engine.run(&mut interpreter, &program).is_err());
+            assert!(engine.run(&mut interpreter, &program).is_err());
+            assert_eq!(interpreter.console_mut().take_output(), "0 \n0 \n", "{}", engine);
+
+            // The frame of the GOSUB is gone, as well as the variables
+            let error = engine.run_from(&mut interpreter, &program, line(200)).err().unwrap();
+            assert!(matches!(error.get_kind(), ErrorKind::ReturnOnEmptyStack), "{}", engine);
+            assert!(interpreter.get_variable("A").is_none());
+
+            let error = engine.run_from(&mut interpreter, &program, line(50)).err().unwrap();
+            assert!(matches!(error.get_kind(), ErrorKind::LineNotFound(_)), "{}", engine);
+
+            assert!(engine.run_from(&mut interpreter, &program, line(20)).is_err());
+            assert!(interpreter.get_variable("A").is_some());
+            interpreter.clear();
+            assert!(interpreter.get_variable("A").is_none());
+            assert!(engine.cont(&mut interpreter, &program).is_err());
+        }
+    }
+}
@@ -20,9 +20,16 @@ use std::cell::OnceCell;
 
 use ascii::AsciiStr;
 
-use crate::tiny_basic::char_stream::AsciiCharStream;
+use crate::tiny_basic::array;
+use crate::tiny_basic::number::Numeric;
 use crate::tiny_basic::types;
 
+/// The line being processed and the current position in it
+pub trait ErrorContext<'ctx> {
+    fn get_stream(&self) -> &'ctx AsciiStr;
+    fn get_location(&self) -> usize;
+}
+
 #[derive(Debug)]
 pub struct Error<'ctx> {
     line_number: Option<types::LineIndex>,
@@ -32,17 +39,27 @@ pub struct Error<'ctx> {
 }
 
 impl<'ctx> Error<'ctx> {
-    pub fn from_context(context: &AsciiCharStream<'ctx>, kind: ErrorKind, line_number: Option<types::LineIndex>) -> Self {
+    pub fn from_context(context: &impl ErrorContext<'ctx>, kind: ErrorKind, line_number: Option<types::LineIndex>) -> Self {
         Self {
-            line_number: line_number,
+            line_number,
             context: OnceCell::from(context.get_stream()),
             location: OnceCell::from(context.get_location()),
-            kind: kind
+            kind
         }
     }
 
-    pub fn set_context(self, context: &AsciiCharStream<'ctx>) -> Self {
-        self.context.set(context.get_stream());
+    /// Error at a location in a line which is not at hand, the line is added later by [`Error::set_context`]
+    pub fn at_location(location: usize, kind: ErrorKind, line_number: Option<types::LineIndex>) -> Self {
+        Self {
+            line_number,
+            context: OnceCell::new(),
+            location: OnceCell::from(location),
+            kind
+        }
+    }
+
+    pub fn set_context(self, context: &impl ErrorContext<'ctx>) -> Self {
+        let _ = self.context.set(context.get_stream());
         self
     }
 
@@ -54,8 +71,19 @@ impl<'ctx> Error<'ctx> {
     pub fn get_kind(&self) -> ErrorKind {
         self.kind.clone()
     }
+
+    pub fn get_line_number(&self) -> Option<types::LineIndex> {
+        self.line_number
+    }
+
+    /// Position of the error in the line, counting from zero
+    pub fn get_location(&self) -> Option<usize> {
+        self.location.get().copied()
+    }
 }
 
+impl std::error::Error for Error<'_> {}
+
 impl<'ctx> From<ErrorKind> for Error<'ctx> {
     fn from(value: ErrorKind) -> Self {
         Self {
@@ -69,20 +97,32 @@ impl<'ctx> From<ErrorKind> for Error<'ctx> {
 
 impl<'ctx> std::fmt::Display for Error<'ctx> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        writeln!(f, "Error: {}", self.kind)?;
+        write!(f, "Error: {}", self.kind)?;
+        // Errors raised outside of any code line (e.g. by RETURN on an empty stack)
+        // may have no context to point at
+        let context = match self.context.get() {
+            Some(context) => context,
+            None => {
+                if let Some(i) = self.line_number {
+                    write!(f, " in line {}", i)?;
+                }
+                return Ok(());
+            }
+        };
+        writeln!(f)?;
         writeln!(f)?;
-        let error_location = match self.line_number {
+        let prefix_length = match self.line_number {
             Some(i) => {
                 write!(f, "{} ", i)?;
                 // The length of the line number in digits 
                 // (which is its log10 + 1) and the space char
-                let i: types::Number = i.into();
+                let i: i16 = i.into();
                 i.checked_ilog10().expect("Line number should be greater than zero") + 1 + 1
             },
             None => 0,
-        } as usize + self.location.get_or_init(|| 0);
-        let context = self.context.get().expect("Error context should be set");
-        let context_length = context.len() + error_location;
+        } as usize;
+        let error_location = prefix_length + self.location.get_or_init(|| 0);
+        let context_length = prefix_length + context.len();
 
         writeln!(f, "{}", context)?;
 
@@ -156,12 +196,43 @@ pub enum ErrorKind {
     ExpectedAsciiInput,
     ExpectedStatement,
     ExpectedCommand,
-    InvalidLineIndex
+    InvalidLineIndex,
+    ExpectedLineIndex,
+    IoError(std::io::ErrorKind),
+    /// The position in the source and the error of each bad line
+    ProgramNotLoaded(Vec<(usize, String)>),
+    NextWithoutFor,
+    InvalidArrayDimension,
+    ArrayAlreadyDimensioned,
+    UndefinedArray,
+    WrongNumberOfSubscripts,
+    SubscriptOutOfRange,
+    TypeMismatch,
+    UnknownFunction,
+    WrongNumberOfArguments,
+    InvalidArgument,
+    Overflow,
+    DivisionByZero,
+    /// The error of each line which failed the syntax check
+    SyntaxErrors(Vec<String>),
+    UnexpectedCharacter,
+    /// Error detected by the instruction at the address of the IL program
+    IlError(usize),
+    /// The program was interrupted with Ctrl-C or stopped by STOP
+    Break,
+    CantContinue,
+    LineNotFound(types::LineIndex)
 }
 
 impl From<std::num::ParseIntError> for ErrorKind {
     fn from(value: std::num::ParseIntError) -> Self {
-        Self::NumberParseError(value.kind().clone())
+        Self::NumberParseError(*value.kind())
+    }
+}
+
+impl From<std::io::Error> for ErrorKind {
+    fn from(value: std::io::Error) -> Self {
+        Self::IoError(value.kind())
     }
 }
 
@@ -190,6 +261,30 @@ impl std::fmt::Display for ErrorKind {
             ErrorKind::ExpectedStatement => write!(f, "Expected statement"),
             ErrorKind::ExpectedCommand => write!(f, "Expected command"),
             ErrorKind::InvalidLineIndex => write!(f, "Line index must be in range [{}; {}]", types::LineIndex::MIN, types::LineIndex::MAX),
+            ErrorKind::ExpectedLineIndex => write!(f, "Expected line index"),
+            ErrorKind::IoError(kind) => write!(f, "Input/output error: {}", kind),
+            ErrorKind::ProgramNotLoaded(bad_lines) => write!(f, "Program was not loaded, {} line(s) could not be parsed", bad_lines.len()),
+            ErrorKind::UnexpectedCharacter => write!(f, "Unexpected character"),
+            ErrorKind::SyntaxErrors(bad_lines) => write!(f, "Syntax errors found in {} line(s)", bad_lines.len()),
+            ErrorKind::NextWithoutFor => write!(f, "NEXT without FOR"),
+            ErrorKind::InvalidArrayDimension => write!(f, "Array bounds must not be negative and the array must have at most {} elements", array::MAX_ELEMENTS),
+            ErrorKind::ArrayAlreadyDimensioned => write!(f, "Array is already dimensioned"),
+            ErrorKind::UndefinedArray => write!(f, "Array must be dimensioned with DIM before use"),
+            ErrorKind::WrongNumberOfSubscripts => write!(f, "Number of subscripts does not match the array dimensions"),
+            ErrorKind::SubscriptOutOfRange => write!(f, "Subscript out of range"),
+            ErrorKind::TypeMismatch => write!(f, "Type mismatch"),
+            ErrorKind::UnknownFunction => write!(f, "Unknown function or array which is not dimensioned"),
+            ErrorKind::WrongNumberOfArguments => write!(f, "Wrong number of arguments passed to the function"),
+            ErrorKind::InvalidArgument => write!(f, "Invalid function argument"),
+            ErrorKind::Overflow => match types::Number::limits() {
+                Some((min, max)) => write!(f, "Arithmetic overflow, the result must be in range [{}; {}]", min, max),
+                None => write!(f, "Arithmetic overflow"),
+            },
+            ErrorKind::DivisionByZero => write!(f, "Division by zero"),
+            ErrorKind::IlError(address) => write!(f, "!{}", address),
+            ErrorKind::Break => write!(f, "Break"),
+            ErrorKind::LineNotFound(line) => write!(f, "There is no line {}", line),
+            ErrorKind::CantContinue => write!(f, "Can't continue, the program has not been stopped or was edited since"),
         }
     }
 }
\ No newline at end of file
@@ -0,0 +1,180 @@
+/*
+    Tiny BASIC interpreter written in Rust
+    Copyright (C) 2025 Artyom Makarov
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use ascii::AsciiStr;
+
+use crate::tiny_basic::error::ErrorKind;
+use crate::tiny_basic::number::Numeric;
+use crate::tiny_basic::random::Prng;
+use crate::tiny_basic::types;
+
+/// Built-in function called as `NAME(arguments)`
+pub struct Function {
+    pub name: &'static str,
+    pub min_arguments: usize,
+    pub max_arguments: usize,
+    body: fn(&[types::Number], &Prng, types::ArithmeticMode) -> Result<types::Number, ErrorKind>
+}
+
+impl Function {
+    /// Calls the function. The number of arguments is expected to be checked by the caller.
+    pub fn call(&self, arguments: &[types::Number], prng: &Prng, arithmetic: types::ArithmeticMode) -> Result<types::Number, ErrorKind> {
+        assert!((self.min_arguments..=self.max_arguments).contains(&arguments.len()));
+        (self.body)(arguments, prng, arithmetic)
+    }
+
+    pub fn accepts(&self, argument_count: usize) -> bool {
+        (self.min_arguments..=self.max_arguments).contains(&argument_count)
+    }
+}
+
+const FUNCTIONS: &[Function] = &[
+    Function {
+        name: "ABS",
+        min_arguments: 1,
+        max_arguments: 1,
+        body: |arguments, _, arithmetic| if arguments[0] < types::Number::zero() {
+            arithmetic.neg(arguments[0].to_owned())
+        } else {
+            Ok(arguments[0].to_owned())
+        }
+    },
+    Function {
+        name: "SGN",
+        min_arguments: 1,
+        max_arguments: 1,
+        body: |arguments, _, _| Ok(arguments[0].sign())
+    },
+    // RND(N) returns a number in range [1; N]
+    Function {
+        name: "RND",
+        min_arguments: 1,
+        max_arguments: 1,
+        body: |arguments, prng, _| {
+            let bound = arguments[0]
+                .to_i64()
+                .and_then(|bound| u64::try_from(bound).ok())
+                .filter(|bound| *bound > 0)
+                .ok_or(ErrorKind::InvalidArgument)?;
+            let value = prng.below(bound) + 1;
+            let value = i64::try_from(value).ok().and_then(types::Number::from_i64);
+            Ok(value.expect("Random number should not exceed the bound"))
+        }
+    },
+    Function {
+        name: "MIN",
+        min_arguments: 2,
+        max_arguments: usize::MAX,
+        body: |arguments, _, _| Ok(arguments.iter().cloned().reduce(|a, b| if b < a { b } else { a }).expect("MIN has at least two arguments"))
+    },
+    Function {
+        name: "MAX",
+        min_arguments: 2,
+        max_arguments: usize::MAX,
+        body: |arguments, _, _| Ok(arguments.iter().cloned().reduce(|a, b| if b > a { b } else { a }).expect("MAX has at least two arguments"))
+    },
+];
+
+pub fn find(name: &AsciiStr) -> Option<&'static Function> {
+    FUNCTIONS
+        .iter()
+        .find(|function| function.name == name.as_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use ascii::AsciiStr;
+
+    use crate::tiny_basic::number::Numeric;
+    use crate::tiny_basic::random::Prng;
+    use crate::tiny_basic::types::{ArithmeticMode, Number};
+
+    use super::find;
+
+    fn call(name: &str, arguments: &[Number]) -> Option<Number> {
+        find(AsciiStr::from_ascii(name).unwrap())
+            .unwrap()
+            .call(arguments, &Prng::default(), ArithmeticMode::default())
+            .ok()
+    }
+
+    #[test]
+    fn test_number_of_arguments() {
+        let accepted = |name: &str| {
+            let function = find(AsciiStr::from_ascii(name).unwrap()).unwrap();
+            (0..5).filter(|count| function.accepts(*count)).collect::<Vec<_>>()
+        };
+        for name in ["ABS", "SGN", "RND"] {
+            assert_eq!(accepted(name), [1], "{}", name);
+        }
+        for name in ["MIN", "MAX"] {
+            assert_eq!(accepted(name), [2, 3, 4], "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_rnd() {
+        let six = [Number::from_i64(6).unwrap()];
+        let roll = |prng: &Prng| find(AsciiStr::from_ascii("RND").unwrap())
+            .unwrap()
+            .call(&six, prng, ArithmeticMode::default())
+            .unwrap();
+        let (first, second) = (Prng::new(42), Prng::new(42));
+        let rolls: Vec<_> = (0..20).map(|_| roll(&first)).collect();
+        assert_eq!(rolls, (0..20).map(|_| roll(&second)).collect::<Vec<_>>());
+        assert!(rolls.iter().all(|number| *number >= Number::one() && *number <= six[0]));
+
+        second.seed(43);
+        assert_ne!(rolls, (0..20).map(|_| roll(&second)).collect::<Vec<_>>());
+    }
+
+    #[test]
+    #[cfg(not(any(feature = "number-f64", feature = "number-big")))]
+    fn test_functions() {
+        assert_eq!(call("ABS", &[-5]), Some(5));
+        assert_eq!(call("ABS", &[Number::MIN]), None);
+        assert_eq!(call("SGN", &[-5]), Some(-1));
+        assert_eq!(call("SGN", &[0]), Some(0));
+        assert_eq!(call("MIN", &[3, -1, 2]), Some(-1));
+        assert_eq!(call("MAX", &[3, -1, 2]), Some(3));
+        assert_eq!(call("RND", &[1]), Some(1));
+        assert_eq!(call("RND", &[0]), None);
+        assert!(find(AsciiStr::from_ascii("SQR").unwrap()).is_none());
+    }
+
+    #[test]
+    #[cfg(all(feature = "number-f64", not(feature = "number-big")))]
+    fn test_functions_of_fractions() {
+        assert_eq!(call("ABS", &[-2.5]), Some(2.5));
+        assert_eq!(call("SGN", &[0.25]), Some(1.0));
+        assert_eq!(call("MIN", &[0.5, -0.5]), Some(-0.5));
+        assert_eq!(call("RND", &[1.9]), Some(1.0));
+        assert_eq!(call("RND", &[0.5]), None);
+    }
+
+    #[test]
+    #[cfg(feature = "number-big")]
+    fn test_functions_of_big_integers() {
+        let big = |text: &str| text.parse::<Number>().unwrap();
+        assert_eq!(call("ABS", &[big("-99999999999999999999")]), Some(big("99999999999999999999")));
+        assert_eq!(call("SGN", &[big("-99999999999999999999")]), Some(big("-1")));
+        assert_eq!(call("MIN", &[big("1"), big("-99999999999999999999")]), Some(big("-99999999999999999999")));
+        assert_eq!(call("RND", &[big("1")]), Some(big("1")));
+        assert_eq!(call("RND", &[big("99999999999999999999")]), None);
+    }
+}
@@ -0,0 +1,796 @@
+/*
+    Tiny BASIC interpreter written in Rust
+    Copyright (C) 2025 Artyom Makarov
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+//! The virtual machine of the Intermediate Language (IL) through which the design note
+//! defines Tiny BASIC, running the IL program published in the note.
+//!
+//! The engine accepts only the language of the note: single letter variables, 16-bit
+//! numbers which wrap around on overflow, no functions, loops or arrays, one statement
+//! per line. As in the historical interpreters, an error is reported by the address of
+//! the IL instruction which detected it, e.g. `!67` for a syntax error.
+
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+use ascii::{AsAsciiStr, AsciiChar, AsciiStr, AsciiString};
+
+use crate::tiny_basic;
+use crate::tiny_basic::char_stream::AsciiCharStream;
+use crate::tiny_basic::console::Console;
+use crate::tiny_basic::error::{Error as TinyBasicError, ErrorKind as TinyBasicErrorKind};
+use crate::tiny_basic::interpreter::{Interpreter, Target};
+use crate::tiny_basic::number::Numeric;
+use crate::tiny_basic::program_storage::ProgramStorage;
+use crate::tiny_basic::types;
+
+/// The IL program of the design note, including the two lines the published listing
+/// originally omitted, which test for `=` in LET and for THEN in IF
+const IL_PROGRAM: &str = r#"
+;THE IL CONTROL SECTION
+
+START:  INIT                  ;INITIALIZE
+        NLINE                 ;WRITE CRLF
+CO:     GETLINE               ;WRITE PROMPT AND GET LINE
+        TSTL    XEC           ;TEST FOR LINE NUMBER
+        INSRT                 ;INSERT IT (MAY BE DELETE)
+        JMP     CO
+XEC:    XINIT                 ;INITIALIZE
+
+;STATEMENT EXECUTOR
+
+STMT:   TST     S1,'LET'      ;IS STATEMENT A LET
+        TSTV    S17           ;YES, PLACE VAR ADDRESS ON AESTK
+        TST     S17,'='
+        CALL    EXPR          ;PLACE EXPR VALUE ON AESTK
+        DONE                  ;REPORT ERROR IF NOT NEXT
+        STORE                 ;STORE RESULT
+        NXT                   ;AND SEQUENCE TO NEXT
+S1:     TST     S3,'GO'       ;GOTO OR GOSUB?
+        TST     S2,'TO'       ;YES...TO, OR...SUB
+        CALL    EXPR          ;GET LABEL
+        DONE                  ;ERROR IF CR NOT NEXT
+        XFER                  ;SET UP AND JUMP
+S2:     TST     S17,'SUB'     ;ERROR IF NO MATCH
+        CALL    EXPR          ;GET DESTINATION
+        DONE                  ;ERROR IF CR NOT NEXT
+        SAV                   ;SAVE RETURN LINE
+        XFER                  ;AND JUMP
+S3:     TST     S8,'PRINT'    ;PRINT
+S4:     TST     S7,'"'        ;TEST FOR QUOTE
+        PRS                   ;PRINT STRING
+S5:     TST     S6,','        ;IS THERE MORE?
+        SPC                   ;SPACE TO NEXT ZONE
+        JMP     S4            ;YES JUMP BACK
+S6:     DONE                  ;ERROR IF CR NOT NEXT
+        NLINE
+        NXT
+S7:     CALL    EXPR
+        PRN                   ;PRINT IT
+        JMP     S5            ;IS THERE MORE?
+S8:     TST     S9,'IF'       ;IF STATEMENT
+        CALL    EXPR          ;GET EXPRESSION
+        CALL    RELOP         ;DETERMINE OPR AND PUT ON STK
+        CALL    EXPR          ;GET EXPRESSION
+        TST     S17,'THEN'
+        CMPR                  ;PERFORM COMPARISON -- PERFORMS NXT IF FALSE
+        JMP     STMT
+S9:     TST     S12,'INPUT'   ;INPUT STATEMENT
+S10:    TSTV    S17           ;GET VAR ADDRESS
+        INNUM                 ;MOVE NUMBER FROM TTY TO AESTK
+        STORE                 ;STORE IT
+        TST     S11,','       ;IS THERE MORE?
+        JMP     S10           ;YES
+S11:    DONE                  ;MUST BE CR
+        NXT                   ;SEQUENCE TO NEXT
+S12:    TST     S13,'RETURN'  ;RETURN STATEMENT
+        DONE                  ;MUST BE CR
+        RSTR                  ;RESTORE LINE NUMBER OF CALL
+        NXT                   ;SEQUENCE TO NEXT STATEMENT
+S13:    TST     S14,'END'
+        FIN
+S14:    TST     S15,'LIST'    ;LIST COMMAND
+        DONE
+        LST
+        NXT
+S15:    TST     S16,'RUN'     ;RUN COMMAND
+        DONE
+        NXT
+S16:    TST     S17,'CLEAR'   ;CLEAR COMMAND
+        DONE
+        JMP     START
+S17:    ERR                   ;SYNTAX ERROR
+
+EXPR:   TST     E0,'-'        ;TEST FOR UNARY -.
+        CALL    TERM          ;GET VALUE
+        NEG                   ;NEGATE IT
+        JMP     E1
+E0:     TST     E1A,'+'       ;TEST FOR UNARY +
+E1A:    CALL    TERM          ;LEADING TERM
+E1:     TST     E2,'+'        ;ANY MORE?
+        CALL    TERM          ;SUM TERM
+        ADD
+        JMP     E1
+E2:     TST     E3,'-'        ;ANY MORE?
+        CALL    TERM          ;DIFFERENCE TERM
+        SUB
+        JMP     E1
+E3:T2:  RTN
+
+TERM:   CALL    FACT
+T0:     TST     T1,'*'
+        CALL    FACT          ;PRODUCT FACTOR.
+        MPY
+        JMP     T0
+T1:     TST     T2,'/'
+        CALL    FACT          ;QUOTIENT FACTOR.
+        DIV
+        JMP     T0
+
+FACT:   TSTV    F0
+        IND                   ;YES, GET THE VALUE.
+        RTN
+F0:     TSTN    F1            ;NUMBER, GET ITS VALUE.
+        RTN
+F1:     TST     F2,'('        ;PARENTHESIZED EXPR.
+        CALL    EXPR
+        TST     F2,')'
+        RTN
+F2:     ERR                   ;ERROR.
+
+RELOP:  TST     R0,'='
+        LIT     0             ;=
+        RTN
+R0:     TST     R4,'<'
+        TST     R1,'='
+        LIT     2             ;<=
+        RTN
+R1:     TST     R3,'>'
+        LIT     3             ;<>
+        RTN
+R3:     LIT     1             ;<
+        RTN
+R4:     TST     S17,'>'
+        TST     R5,'='
+        LIT     5             ;>=
+        RTN
+R5:     TST     R6,'<'
+        LIT     3             ;><
+        RTN
+R6:     LIT     4             ;>
+        RTN
+"#;
+
+/// Address of an instruction in the IL program
+pub type IlAddress = usize;
+
+/// Width of a print zone, to which a comma in PRINT advances
+const ZONE_WIDTH: usize = 8;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum IlInstruction {
+    /// TST: jumps if the text does not start with the string, skips it otherwise
+    Test(IlAddress, &'static str),
+    Call(IlAddress),
+    Return,
+    /// DONE: reports an error if the line is not over
+    Done,
+    Jump(IlAddress),
+    PrintString,
+    PrintNumber,
+    Space,
+    NewLine,
+    /// NXT: goes on with the following line
+    Next,
+    /// XFER: goes on with the line whose number is on the stack
+    Transfer,
+    Save,
+    Restore,
+    /// CMPR: goes on with the following line if the comparison is false
+    Compare,
+    Literal(i16),
+    InputNumber,
+    Finish,
+    Error,
+    Add,
+    Subtract,
+    Negate,
+    Multiply,
+    Divide,
+    Store,
+    TestVariable(IlAddress),
+    TestNumber(IlAddress),
+    Indirect,
+    List,
+    Init,
+    GetLine,
+    TestLine(IlAddress),
+    Insert,
+    ExecutionInit
+}
+
+struct IlProgram {
+    code: Vec<IlInstruction>,
+    labels: HashMap<&'static str, IlAddress>
+}
+
+impl IlProgram {
+    fn get() -> &'static IlProgram {
+        static PROGRAM: OnceLock<IlProgram> = OnceLock::new();
+        PROGRAM.get_or_init(|| IlProgram::assemble(IL_PROGRAM))
+    }
+
+    /// Assembles a listing, panicking on any mistake in it since the listing is built in
+    fn assemble(listing: &'static str) -> Self {
+        let lines: Vec<(Vec<&'static str>, Option<&'static str>)> = listing
+            .lines()
+            .map(|line| {
+                let mut rest = strip_comment(line).trim();
+                let mut labels = Vec::new();
+                loop {
+                    let (word, tail) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+                    if !word.ends_with(':') {
+                        break;
+                    }
+                    labels.extend(word.split(':').filter(|label| !label.is_empty()));
+                    rest = tail.trim();
+                }
+                (labels, (!rest.is_empty()).then_some(rest))
+            })
+            .collect();
+
+        let mut labels = HashMap::new();
+        let mut address = 0;
+        for (line_labels, instruction) in &lines {
+            for label in line_labels {
+                assert!(labels.insert(*label, address).is_none(), "Label {} is defined twice", label);
+            }
+            if instruction.is_some() {
+                address += 1;
+            }
+        }
+
+        let target = |label: &str| -> IlAddress {
+            *labels.get(label.trim()).unwrap_or_else(|| panic!("Label {} is not defined", label))
+        };
+        let code = lines
+            .iter()
+            .filter_map(|(_, instruction)| *instruction)
+            .map(|instruction| {
+                let (mnemonic, operands) = instruction
+                    .split_once(char::is_whitespace)
+                    .map_or((instruction, ""), |(mnemonic, operands)| (mnemonic, operands.trim()));
+                match mnemonic {
+                    "TST" => {
+                        let (label, string) = operands.split_once(',').expect("TST should have a label and a string");
+                        let string = string
+                            .trim()
+                            .strip_prefix('\'')
+                            .and_then(|string| string.strip_suffix('\''))
+                            .expect("String should be quoted");
+                        IlInstruction::Test(target(label), string)
+                    },
+                    "CALL" => IlInstruction::Call(target(operands)),
+                    "RTN" => IlInstruction::Return,
+                    "DONE" => IlInstruction::Done,
+                    "JMP" => IlInstruction::Jump(target(operands)),
+                    "PRS" => IlInstruction::PrintString,
+                    "PRN" => IlInstruction::PrintNumber,
+                    "SPC" => IlInstruction::Space,
+                    "NLINE" => IlInstruction::NewLine,
+                    "NXT" => IlInstruction::Next,
+                    "XFER" => IlInstruction::Transfer,
+                    "SAV" => IlInstruction::Save,
+                    "RSTR" => IlInstruction::Restore,
+                    "CMPR" => IlInstruction::Compare,
+                    "LIT" => IlInstruction::Literal(operands.parse().expect("LIT should have a number")),
+                    "INNUM" => IlInstruction::InputNumber,
+                    "FIN" => IlInstruction::Finish,
+                    "ERR" => IlInstruction::Error,
+                    "ADD" => IlInstruction::Add,
+                    "SUB" => IlInstruction::Subtract,
+                    "NEG" => IlInstruction::Negate,
+                    "MPY" => IlInstruction::Multiply,
+                    "DIV" => IlInstruction::Divide,
+                    "STORE" => IlInstruction::Store,
+                    "TSTV" => IlInstruction::TestVariable(target(operands)),
+                    "TSTN" => IlInstruction::TestNumber(target(operands)),
+                    "IND" => IlInstruction::Indirect,
+                    "LST" => IlInstruction::List,
+                    "INIT" => IlInstruction::Init,
+                    "GETLINE" => IlInstruction::GetLine,
+                    "TSTL" => IlInstruction::TestLine(target(operands)),
+                    "INSRT" => IlInstruction::Insert,
+                    "XINIT" => IlInstruction::ExecutionInit,
+                    _ => panic!("Unknown IL instruction {}", mnemonic),
+                }
+            })
+            .collect();
+
+        Self {
+            code,
+            labels
+        }
+    }
+
+    fn label(&self, label: &str) -> IlAddress {
+        self.labels[label]
+    }
+}
+
+/// Removes the comment from a line of the listing, a semicolon in quotes not starting one
+fn strip_comment(line: &str) -> &str {
+    let mut quoted = false;
+    for (i, ch) in line.char_indices() {
+        match ch {
+            '\'' => quoted = !quoted,
+            ';' if !quoted => return &line[..i],
+            _ => (),
+        }
+    }
+    line
+}
+
+/// State of the IL machine, which keeps only the variables in the [`Interpreter`]
+struct IlMachine<'p> {
+    program: &'p ProgramStorage,
+    pc: IlAddress,
+    /// Line being executed and the position of the cursor in it
+    line: types::LineIndex,
+    source: &'p AsciiStr,
+    cursor: usize,
+    /// The arithmetic expression stack (AESTK)
+    expression_stack: Vec<i16>,
+    /// Return addresses of the IL subroutines
+    control_stack: Vec<IlAddress>,
+    /// Lines of the GOSUBs (SBRSTK)
+    subroutine_stack: Vec<types::LineIndex>,
+    /// Position of the print head
+    column: usize
+}
+
+impl<'p> IlMachine<'p> {
+    fn pop(&mut self) -> i16 {
+        self.expression_stack.pop().expect("IL program should push before it pops")
+    }
+
+    fn peek(&self) -> Option<AsciiChar> {
+        self.source.get_ascii(self.cursor)
+    }
+
+    fn skip_blanks(&mut self) {
+        while self.peek() == Some(AsciiChar::Space) {
+            self.cursor += 1;
+        }
+    }
+
+    /// Moves the cursor to the start of a line, returning false if there is no such line
+    fn go_to_line(&mut self, line: types::LineIndex) -> bool {
+        match self.program.get_line(line) {
+            Some(source) => {
+                self.line = line;
+                self.source = source;
+                self.cursor = 0;
+                true
+            },
+            None => false,
+        }
+    }
+
+    /// Starts executing the following line, returning false if the program is over
+    fn next_line(&mut self, il: &IlProgram) -> bool {
+        match self.program.get_following_line_index(self.line) {
+            Some(line) => {
+                self.go_to_line(line);
+                self.pc = il.label("XEC");
+                true
+            },
+            None => false,
+        }
+    }
+
+    /// Skips the text if it follows the cursor, blanks being ignored as in `GO TO`
+    fn test(&mut self, text: &str) -> bool {
+        let start = self.cursor;
+        for expected in text.bytes() {
+            self.skip_blanks();
+            if self.peek().map(AsciiChar::as_byte) != Some(expected) {
+                self.cursor = start;
+                return false;
+            }
+            self.cursor += 1;
+        }
+        true
+    }
+}
+
+impl<'line_source, C: Console> Interpreter<C> {
+    /// Runs a program from its first line with the IL program of the design note,
+    /// after clearing the variables
+    pub fn run_il(&mut self, program: &'line_source ProgramStorage) -> tiny_basic::Result<'line_source, ()> {
+        match program.get_first_line_index() {
+            Some(line) => self.run_il_from(program, line),
+            None => {
+                self.clear();
+                Ok(())
+            },
+        }
+    }
+
+    /// Runs a program from a line with the IL program of the design note, after clearing the variables
+    pub fn run_il_from(&mut self, program: &'line_source ProgramStorage, line: types::LineIndex) -> tiny_basic::Result<'line_source, ()> {
+        self.clear();
+        let il = IlProgram::get();
+        let source = program
+            .get_line(line)
+            .ok_or(TinyBasicError::from(TinyBasicErrorKind::LineNotFound(line)))?;
+        let mut machine = IlMachine {
+            program,
+            pc: il.label("XEC"),
+            line,
+            source,
+            cursor: 0,
+            expression_stack: Vec::new(),
+            control_stack: Vec::new(),
+            subroutine_stack: Vec::new(),
+            column: 0
+        };
+
+        let statement_executor = il.label("XEC");
+        self.take_interrupt();
+        loop {
+            let address = machine.pc;
+            if address == statement_executor && self.take_interrupt() {
+                return Err(TinyBasicError::from(TinyBasicErrorKind::Break).set_line_number(Some(machine.line)));
+            }
+            match self.il_step(il, &mut machine) {
+                Ok(true) => (),
+                Ok(false) => return Ok(()),
+                Err(kind) => {
+                    let context = AsciiCharStream::from_ascii_str(machine.source);
+                    return Err(TinyBasicError::at_location(machine.cursor, kind.unwrap_or(TinyBasicErrorKind::IlError(address)), Some(machine.line))
+                        .set_context(&context));
+                },
+            }
+        }
+    }
+
+    /// Executes one IL instruction, returning whether the program goes on.
+    /// An error of the program itself is `None`, to be reported with the address of the instruction.
+    fn il_step(&mut self, il: &IlProgram, machine: &mut IlMachine<'line_source>) -> Result<bool, Option<TinyBasicErrorKind>> {
+        let instruction = &il.code[machine.pc];
+        machine.pc += 1;
+        match instruction {
+            IlInstruction::Test(target, text) => {
+                if !machine.test(text) {
+                    machine.pc = *target;
+                }
+            },
+            IlInstruction::Call(target) => {
+                machine.control_stack.push(machine.pc);
+                machine.pc = *target;
+            },
+            IlInstruction::Return => {
+                machine.pc = machine.control_stack.pop().ok_or(None)?;
+            },
+            IlInstruction::Done => {
+                machine.skip_blanks();
+                if machine.peek().is_some() {
+                    return Err(None);
+                }
+            },
+            IlInstruction::Jump(target) => machine.pc = *target,
+            IlInstruction::PrintString => {
+                let start = machine.cursor;
+                let length = machine.source[start..]
+                    .chars()
+                    .position(|ch| ch == AsciiChar::Quotation)
+                    .ok_or(None)?;
+                machine.cursor += length + 1;
+                self.il_write(machine, machine.source[start..start + length].as_str())?;
+            },
+            IlInstruction::PrintNumber => {
+                let number = machine.pop();
+                self.il_write(machine, &number.to_string())?;
+            },
+            IlInstruction::Space => {
+                let spaces = ZONE_WIDTH - machine.column % ZONE_WIDTH;
+                self.il_write(machine, &" ".repeat(spaces))?;
+            },
+            IlInstruction::NewLine => {
+                self.il_write(machine, "\n")?;
+                machine.column = 0;
+            },
+            IlInstruction::Next => return Ok(machine.next_line(il)),
+            IlInstruction::Transfer => {
+                let line = types::LineIndex::try_from(machine.pop()).map_err(|_| None)?;
+                if !machine.go_to_line(line) {
+                    return Err(None);
+                }
+                machine.pc = il.label("XEC");
+            },
+            IlInstruction::Save => machine.subroutine_stack.push(machine.line),
+            IlInstruction::Restore => {
+                let line = machine.subroutine_stack.pop().ok_or(None)?;
+                if !machine.go_to_line(line) {
+                    return Err(None);
+                }
+            },
+            IlInstruction::Compare => {
+                let rhs = machine.pop();
+                let operator = machine.pop();
+                let lhs = machine.pop();
+                let holds = match operator {
+                    0 => lhs == rhs,
+                    1 => lhs < rhs,
+                    2 => lhs <= rhs,
+                    3 => lhs != rhs,
+                    4 => lhs > rhs,
+                    5 => lhs >= rhs,
+                    _ => unreachable!("RELOP should push a code from 0 to 5"),
+                };
+                if !holds {
+                    return Ok(machine.next_line(il));
+                }
+            },
+            IlInstruction::Literal(value) => machine.expression_stack.push(*value),
+            IlInstruction::InputNumber => {
+                self.il_write(machine, "? ")?;
+                machine.column = 0;
+                let input = self.get_user_input().map_err(|error| Some(error.get_kind()))?;
+                let number = parse_number(input.as_str()).ok_or(None)?;
+                machine.expression_stack.push(number);
+            },
+            IlInstruction::Finish => return Ok(false),
+            IlInstruction::Error => return Err(None),
+            IlInstruction::Add => {
+                let rhs = machine.pop();
+                let lhs = machine.pop();
+                machine.expression_stack.push(lhs.wrapping_add(rhs));
+            },
+            IlInstruction::Subtract => {
+                let rhs = machine.pop();
+                let lhs = machine.pop();
+                machine.expression_stack.push(lhs.wrapping_sub(rhs));
+            },
+            IlInstruction::Negate => {
+                let value = machine.pop();
+                machine.expression_stack.push(value.wrapping_neg());
+            },
+            IlInstruction::Multiply => {
+                let rhs = machine.pop();
+                let lhs = machine.pop();
+                machine.expression_stack.push(lhs.wrapping_mul(rhs));
+            },
+            IlInstruction::Divide => {
+                let rhs = machine.pop();
+                let lhs = machine.pop();
+                if rhs == 0 {
+                    return Err(None);
+                }
+                machine.expression_stack.push(lhs.wrapping_div(rhs));
+            },
+            IlInstruction::Store => {
+                let value = machine.pop();
+                let variable = machine.pop();
+                let value = types::Number::from_i64(value.into()).expect("16-bit number should fit into any number");
+                self.assign(Target::Variable(variable_name(variable)), value.into());
+            },
+            IlInstruction::TestVariable(target) => {
+                machine.skip_blanks();
+                match machine.peek().filter(AsciiChar::is_ascii_uppercase) {
+                    Some(letter) => {
+                        machine.cursor += 1;
+                        machine.expression_stack.push(i16::from(letter.as_byte() - b'A'));
+                    },
+                    None => machine.pc = *target,
+                }
+            },
+            IlInstruction::TestNumber(target) => {
+                machine.skip_blanks();
+                let digits = machine.source[machine.cursor..]
+                    .chars()
+                    .take_while(AsciiChar::is_ascii_digit)
+                    .count();
+                if digits == 0 {
+                    machine.pc = *target;
+                } else {
+                    let number = machine.source[machine.cursor..machine.cursor + digits]
+                        .chars()
+                        .fold(0i16, |number, digit| number.wrapping_mul(10).wrapping_add(i16::from(digit.as_byte() - b'0')));
+                    machine.cursor += digits;
+                    machine.expression_stack.push(number);
+                }
+            },
+            IlInstruction::Indirect => {
+                let variable = machine.pop();
+                let value = match self.variable(&variable_name(variable)) {
+                    // Only the numbers which fit into 16 bits are written by the IL machine
+                    types::Value::Number(number) => number.to_i64().unwrap_or(0) as i16,
+                    types::Value::String(_) => unreachable!("Single letter variables should be numeric"),
+                };
+                machine.expression_stack.push(value);
+            },
+            IlInstruction::List => {
+                let listing = machine.program.to_source();
+                self.il_write(machine, &listing)?;
+                machine.column = 0;
+            },
+            IlInstruction::Init => {
+                machine.expression_stack.clear();
+                machine.control_stack.clear();
+                machine.subroutine_stack.clear();
+            },
+            // Getting a line returns to the prompt, so the run is over
+            IlInstruction::GetLine => return Ok(false),
+            IlInstruction::TestLine(_) | IlInstruction::Insert => unreachable!("Lines are only collected after GETLINE"),
+            IlInstruction::ExecutionInit => machine.expression_stack.clear(),
+        }
+        Ok(true)
+    }
+
+    fn il_write(&mut self, machine: &mut IlMachine<'line_source>, text: &str) -> Result<(), Option<TinyBasicErrorKind>> {
+        machine.column += text.len();
+        self.write(text).map_err(|error| Some(error.get_kind()))
+    }
+}
+
+/// Name of the variable at an address on the expression stack
+fn variable_name(address: i16) -> AsciiString {
+    let letter = AsciiChar::from_ascii(b'A' + address as u8).expect("Variable address should be a letter");
+    AsciiString::from(vec![letter])
+}
+
+/// Parses a number typed in by the user, with an optional sign
+fn parse_number(input: &str) -> Option<i16> {
+    let (negative, digits) = match input.trim().strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, input.trim().strip_prefix('+').unwrap_or(input.trim())),
+    };
+    if digits.is_empty() || !digits.bytes().all(|digit| digit.is_ascii_digit()) {
+        return None;
+    }
+    let number = digits
+        .bytes()
+        .fold(0i16, |number, digit| number.wrapping_mul(10).wrapping_add(i16::from(digit - b'0')));
+    Some(if negative { number.wrapping_neg() } else { number })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tiny_basic::console::BufferConsole;
+    #[cfg(not(feature = "number-f64"))]
+    use crate::tiny_basic::engine::Engine;
+    use crate::tiny_basic::error::ErrorKind;
+    use crate::tiny_basic::interpreter::Interpreter;
+    use crate::tiny_basic::program_storage::ProgramStorage;
+    use crate::tiny_basic::types::LineIndex;
+
+    use super::{IlInstruction, IlProgram};
+
+    /// Address of the IL instruction which detected an error, and the line of the error
+    type IlFailure = (usize, Option<LineIndex>);
+
+    fn run(source: &str, input: &str) -> (Interpreter<BufferConsole>, Result<(), IlFailure>) {
+        let program = ProgramStorage::load(source).unwrap();
+        let mut interpreter = Interpreter::with_console(BufferConsole::with_input(input));
+        let result = interpreter
+            .run_il(&program)
+            .map_err(|error| match error.get_kind() {
+                ErrorKind::IlError(address) => (address, error.get_line_number()),
+                kind => panic!("Unexpected error: {}", kind),
+            });
+        (interpreter, result)
+    }
+
+    fn output(source: &str, input: &str) -> String {
+        let (mut interpreter, result) = run(source, input);
+        assert!(result.is_ok());
+        interpreter.console_mut().take_output()
+    }
+
+    #[test]
+    fn test_assemble() {
+        let il = IlProgram::get();
+        assert_eq!(il.code[il.label("START")], IlInstruction::Init);
+        assert_eq!(il.code[il.label("S17")], IlInstruction::Error);
+        assert_eq!(il.label("E3"), il.label("T2"));
+        assert_eq!(il.code[il.label("S4")], IlInstruction::Test(il.label("S7"), "\""));
+        assert_eq!(il.code[il.label("S5")], IlInstruction::Test(il.label("S6"), ","));
+    }
+
+    #[test]
+    fn test_statements() {
+        assert_eq!(output("10 PRINT \"A\", 1, -2 * (3 + 4) / 5\n20 PRINT \"\"", ""), "A       1       -2\n\n");
+        assert_eq!(output("10 LET A = 3\n20 GO TO 40\n30 LET A = 0\n40 GOSUB 100\n50 PRINT A\n60 END\n100 LET A = A * A\n110 RETURN", ""), "9\n");
+        assert_eq!(output("10 LET I = 1\n20 PRINT I\n30 LET I = I + 1\n40 IF I < 4 THEN GOTO 20\n50 IF I >< 4 THEN PRINT 0", ""), "1\n2\n3\n");
+        assert_eq!(output("10 INPUT A, B\n20 PRINT A - B", "7\n-5\n"), "? ? 12\n");
+        // Numbers have 16 bits and wrap around, as in the original interpreters
+        assert_eq!(output("10 PRINT 32767 + 1, 200 * 200", ""), "-32768  -25536\n");
+    }
+
+    #[test]
+    fn test_errors() {
+        let il = IlProgram::get();
+        let syntax_error = il.label("S17");
+        let line = |line| LineIndex::try_from(line).ok();
+        assert_eq!(run("10 PRINT 1\n20 FOR I = 1 TO 2", "").1, Err((syntax_error, line(20))));
+        assert_eq!(run("10 LET A$ = 1", "").1, Err((syntax_error, line(10))));
+        // The unary minus may only start an expression
+        assert_eq!(run("10 PRINT 1 - -1", "").1, Err((il.label("F2"), line(10))));
+        // PRINT has no semicolons, or no empty list
+        assert_eq!(run("10 PRINT 1;", "").1, Err((il.label("S6"), line(10))));
+        assert_eq!(run("10 PRINT", "").1, Err((il.label("F2"), line(10))));
+        assert_eq!(run("10 PRINT (1", "").1, Err((il.label("F2"), line(10))));
+        assert_eq!(run("10 GOTO 15\n20 END", "").1, Err((il.label("S1") + 4, line(10))));
+        assert_eq!(run("10 RETURN", "").1, Err((il.label("S12") + 2, line(10))));
+        assert_eq!(run("10 PRINT 1 / 0", "").1, Err((il.label("T1") + 2, line(10))));
+
+        let (mut interpreter, result) = run("10 PRINT 1\n20 LET A = 1 +", "");
+        assert!(result.is_err());
+        assert_eq!(interpreter.console_mut().take_output(), "1\n");
+    }
+
+    /// The items printed on each line. The IL program prints in zones of 8 columns,
+    /// as the note does, while the native engines separate the items by a blank.
+    /// The prompts of INPUT are left out, as the native engines name the variable in them.
+    #[cfg(not(feature = "number-f64"))]
+    fn printed_items(output: &str) -> Vec<Vec<&str>> {
+        output
+            .lines()
+            .map(|line| line.split_whitespace().filter(|item| !item.ends_with('?')).collect())
+            .collect()
+    }
+
+    /// The IL program serves as a reference for the native engines on the language of the design note:
+    /// they must print the same items, stop with an error on the same programs and leave the same variables
+    #[test]
+    #[cfg(not(feature = "number-f64"))]
+    fn test_native_agrees_with_reference() {
+        let programs = [
+            ("10 LET A = 10\n20 LET B = 0\n30 LET B = B + A * A\n40 LET A = A - 1\n50 IF A > 0 THEN GOTO 30\n60 PRINT \"SUM\", B", ""),
+            ("10 LET N = 12\n20 LET A = 0\n30 LET B = 1\n40 GOSUB 100\n50 LET N = N - 1\n60 IF N <> 0 THEN GOTO 40\n70 END\n100 LET C = A + B\n105 PRINT C\n110 LET A = B\n120 LET B = C\n130 RETURN", ""),
+            ("10 LET A = -7 / 2\n20 LET B = 7 / (-2) * 2 + 7 - (3 - 1) * 4\n30 LET C = +5 - (-A)\n40 PRINT A, B, C, -A * (C - B)", ""),
+            ("10 LET X = 1000\n20 LET Y = 1\n30 IF X / Y <= Y THEN GOTO 70\n40 LET Y = (Y + X / Y) / 2\n50 LET Z = Z + 1\n60 GOTO 30\n70 PRINT \"ROOT\", Y, \"STEPS\", Z", ""),
+            ("10 LET A = 3\n20 IF A = 3 THEN PRINT 1\n30 IF A <> 3 THEN PRINT 2\n40 IF A >< 4 THEN PRINT 3\n50 IF A < 3 THEN PRINT 4\n60 IF A >= 3 THEN PRINT 5\n70 IF A <= 2 THEN PRINT 6\n80 IF A > 2 THEN PRINT 7", ""),
+            ("10 INPUT A, B\n20 PRINT \"A+B\", A + B\n30 INPUT C\n40 PRINT C * (A - B)", "7\n-5\n12\n"),
+            ("10 GOSUB 100\n20 PRINT 20\n30 END\n100 GOSUB 200\n110 PRINT 110\n120 RETURN\n200 PRINT 200\n210 RETURN", ""),
+            ("10 LET I = 5\n20 PRINT I\n25 PRINT 100 / I\n30 LET I = I - 1\n40 GOTO 20", ""),
+            ("10 PRINT 1\n20 RETURN\n30 PRINT 2", ""),
+        ];
+        for (source, input) in programs {
+            let program = ProgramStorage::load(source).unwrap();
+            let mut reference = Interpreter::with_console(BufferConsole::with_input(input));
+            let reference_result = reference.run_il(&program);
+            let reference_output = reference.console_mut().take_output();
+            let mut expected: Vec<_> = reference.variables().map(|(name, value)| (name.to_string(), value.to_string())).collect();
+            expected.sort();
+
+            for engine in Engine::ALL {
+                let mut native = Interpreter::with_console(BufferConsole::with_input(input));
+                let native_result = engine.run(&mut native, &program);
+                assert_eq!(native_result.is_err(), reference_result.is_err(), "{}: {}", engine, source);
+                let native_output = native.console_mut().take_output();
+                assert_eq!(printed_items(&native_output), printed_items(&reference_output), "{}: {}", engine, source);
+
+                let mut actual: Vec<_> = native.variables().map(|(name, value)| (name.to_string(), value.to_string())).collect();
+                actual.sort();
+                assert_eq!(expected, actual, "{}: {}", engine, source);
+            }
+        }
+    }
+}
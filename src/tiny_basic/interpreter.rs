@@ -16,177 +16,531 @@
     along with this program.  If not, see <https://www.gnu.org/licenses/>.
 */
 
-use std::io::{stdin, stdout, Write};
 use std::collections::HashMap;
+use std::sync::atomic::AtomicBool;
 
-use ascii::{AsAsciiStr, AsciiChar, AsciiString};
+use ascii::{AsAsciiStr, AsciiStr, AsciiString};
 
 use crate::tiny_basic;
 use crate::tiny_basic::types;
+use crate::tiny_basic::array::Array;
+use crate::tiny_basic::functions;
+use crate::tiny_basic::interrupt;
+use crate::tiny_basic::number::Numeric;
+use crate::tiny_basic::random::Prng;
 use crate::tiny_basic::error::{Error as TinyBasicError, ErrorKind as TinyBasicErrorKind};
 use crate::tiny_basic::program_storage::ProgramStorage;
+use crate::tiny_basic::console::{Console, StdConsole};
 
+use crate::tiny_basic::ast::{self, ExpressionKind, StatementKind};
+use crate::tiny_basic::char_stream::{ArithmeticOperator, AsciiCharStream, RelationalOperator};
+use crate::tiny_basic::parser;
+use crate::tiny_basic::bytecode::Bytecode;
+use crate::tiny_basic::vm::Machine;
 
-use crate::tiny_basic::char_stream::AsciiCharStream;
+type Environment = HashMap<AsciiString, types::Value>;
+type Arrays = HashMap<AsciiString, Array>;
+type ReturnStack = Vec<ReturnAddress>;
+type LoopStack = Vec<LoopFrame<StatementAddress>>;
 
-use crate::tiny_basic::char_stream::Keyword;
+/// Location of a statement: the line and the position of the statement in it
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct StatementAddress {
+    line: types::LineIndex,
+    offset: usize
+}
+
+/// Where RETURN goes back to
+#[derive(Clone, Copy, Debug)]
+enum ReturnAddress {
+    Statement(StatementAddress),
+    /// The statement following a GOSUB typed at the prompt, by its position in the line
+    Immediate(usize)
+}
+
+/// An active FOR loop, `A` being how the engine addresses the statements
+#[derive(Debug)]
+pub(crate) struct LoopFrame<A> {
+    variable: AsciiString,
+    limit: types::Number,
+    step: types::Number,
+    /// The statement following FOR
+    body: A
+}
+
+/// Where a paused program resumes, in the terms of the engine which paused it
+pub(crate) enum PausedState {
+    Tree(StatementAddress),
+    Bytecode(Machine)
+}
+
+/// A program paused by STOP, END, a break or an error, which CONT resumes unless the program was edited
+struct Paused {
+    state: PausedState,
+    revision: u64
+}
 
-use super::char_stream::Statement;
+/// What LET and INPUT assign to
+pub(crate) enum Target {
+    Variable(AsciiString),
+    Element(AsciiString, usize)
+}
 
-type Environment = HashMap<AsciiString, types::Number>;
-type ReturnStack = Vec<types::LineIndex>;
+impl Target {
+    fn is_string(&self) -> bool {
+        match self {
+            Target::Variable(name) | Target::Element(name, _) => types::Value::is_string_name(name),
+        }
+    }
+}
 
-pub struct Interpreter {
+/// Executes the trees of the lines of a program.
+///
+/// The variables, the arrays and the console are also used by the bytecode engine,
+/// which shares the operations below with the tree walker, see [`Engine`](crate::Engine).
+pub struct Interpreter<C: Console = StdConsole> {
     next_line_to_execute: Option<types::LineIndex>,
+    /// Position of the statement to resume from in `next_line_to_execute`
+    next_statement_offset: usize,
+    /// Set by the statements which jump and by an IF whose condition is false,
+    /// the rest of the line is not executed then
+    control_transferred: bool,
     current_line_number: Option<types::LineIndex>,
+    /// Position of the statement being executed in the current line
+    current_statement: usize,
+    /// Set when RETURN goes back to the line typed at the prompt, the position of the statement to resume
+    immediate_return: Option<usize>,
+    paused: Option<Paused>,
     environment: Environment,
-    return_stack: ReturnStack
+    arrays: Arrays,
+    return_stack: ReturnStack,
+    loop_stack: LoopStack,
+    random: Prng,
+    arithmetic: types::ArithmeticMode,
+    interrupt: &'static AtomicBool,
+    console: C
 }
 
-impl<'line_source> Interpreter {
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Interpreter {
+    /// Creates an interpreter which uses the standard input and output
     pub fn new() -> Self {
+        Self::with_console(StdConsole)
+    }
+}
+
+impl<'line_source, C: Console> Interpreter<C> {
+    pub fn with_console(console: C) -> Self {
         Interpreter {
             environment: Environment::new(),
+            arrays: Arrays::new(),
             next_line_to_execute: None,
+            next_statement_offset: 0,
+            control_transferred: false,
             current_line_number: None,
-            return_stack: ReturnStack::new()
+            current_statement: 0,
+            immediate_return: None,
+            paused: None,
+            return_stack: ReturnStack::new(),
+            loop_stack: LoopStack::new(),
+            random: Prng::default(),
+            arithmetic: types::ArithmeticMode::default(),
+            interrupt: &interrupt::INTERRUPTED,
+            console
         }
     }
 
+    pub fn set_arithmetic_mode(&mut self, mode: types::ArithmeticMode) {
+        self.arithmetic = mode;
+    }
+
+    /// Sets the flag which interrupts a running program when raised,
+    /// by default the one raised by the handler of Ctrl-C
+    pub fn set_interrupt_flag(&mut self, flag: &'static AtomicBool) {
+        self.interrupt = flag;
+    }
+
+    pub fn console(&self) -> &C {
+        &self.console
+    }
+
+    pub fn console_mut(&mut self) -> &mut C {
+        &mut self.console
+    }
+
+    /// Runs the program from its first line, after clearing the variables and the stacks as [`Interpreter::clear`] does
     pub fn run(&mut self, program: &'line_source ProgramStorage) -> tiny_basic::Result<'line_source, ()> {
         match program.get_first_line_index() {
-            Some(index) => {
-                self.next_line_to_execute = Some(index);
+            Some(line) => self.run_from(program, line),
+            None => {
+                self.clear();
+                Ok(())
             },
-            None => return Ok(()),
         }
-        
+    }
+
+    /// Runs the program from a line, after clearing the variables and the stacks as [`Interpreter::clear`] does
+    pub fn run_from(&mut self, program: &'line_source ProgramStorage, line: types::LineIndex) -> tiny_basic::Result<'line_source, ()> {
+        self.clear();
+        if program.get_line(line).is_none() {
+            return Err(TinyBasicError::from(TinyBasicErrorKind::LineNotFound(line)));
+        }
+        self.next_line_to_execute = Some(line);
+        self.next_statement_offset = 0;
+
+        self.take_interrupt();
+        let result = self.run_lines(program);
+        self.current_line_number = None;
+        result
+    }
+
+    /// Forgets the variables, the arrays, the GOSUB and FOR stacks and the paused program
+    pub fn clear(&mut self) {
+        self.environment.clear();
+        self.arrays.clear();
+        self.return_stack.clear();
+        self.loop_stack.clear();
+        self.paused = None;
+        self.next_line_to_execute = None;
+        self.next_statement_offset = 0;
+        self.current_line_number = None;
+    }
+
+    /// Resumes the program where STOP, END, a break or an error paused it,
+    /// on the engine which was running it
+    pub fn cont(&mut self, program: &'line_source ProgramStorage) -> tiny_basic::Result<'line_source, ()> {
+        let address = match self.take_paused(program)? {
+            PausedState::Tree(address) => address,
+            PausedState::Bytecode(machine) => {
+                self.take_interrupt();
+                return self.run_machine(&Bytecode::compile(program), machine);
+            },
+        };
+        self.next_line_to_execute = Some(address.line);
+        self.next_statement_offset = address.offset;
+
+        self.take_interrupt();
+        let result = self.run_lines(program);
+        self.current_line_number = None;
+        result
+    }
+
+    fn run_lines(&mut self, program: &'line_source ProgramStorage) -> tiny_basic::Result<'line_source, ()> {
         while let Some(current_line) = self.next_line_to_execute {
+            if self.take_interrupt() {
+                self.pause(PausedState::Tree(StatementAddress { line: current_line, offset: self.next_statement_offset }), program);
+                return Err(TinyBasicError::from(TinyBasicErrorKind::Break).set_line_number(Some(current_line)));
+            }
+            let offset = std::mem::take(&mut self.next_statement_offset);
             self.current_line_number = Some(current_line);
+            self.current_statement = offset;
             self.next_line_to_execute = program.get_following_line_index(current_line);
 
-            if let Some(line) = program.get_line(current_line) {
-                self.execute(&mut AsciiCharStream::from_ascii_str(line))?;
+            if let (Some(source), Some(statements)) = (program.get_line(current_line), program.get_statements(current_line)) {
+                let context = AsciiCharStream::from_ascii_str(source);
+                let result = statements.and_then(|statements| self.execute_statements(statements, offset));
+                if let Err(error) = result {
+                    // END and STOP resume with the following statement, an error retries the failed one
+                    let offset = match error.get_kind() {
+                        TinyBasicErrorKind::ExecutionReachedEnd | TinyBasicErrorKind::Break => self.current_statement + 1,
+                        _ => self.current_statement,
+                    };
+                    self.pause(PausedState::Tree(StatementAddress { line: current_line, offset }), program);
+                    return match error.get_kind() {
+                        TinyBasicErrorKind::ExecutionReachedEnd => Ok(()),
+                        _ => Err(error.set_context(&context).set_line_number(Some(current_line))),
+                    };
+                }
             }
         }
 
         Ok(())
     }
 
-    pub fn execute(&mut self, stmt: &mut AsciiCharStream<'line_source>) -> tiny_basic::Result<'line_source, ()> {
-        let statement = 
-            stmt
-            .consume_statement()
-            .ok_or(TinyBasicError::from_context(stmt, TinyBasicErrorKind::ExpectedStatement, self.current_line_number))?;
-
-        match statement {
-            Statement::Print => self.print_stmt(stmt),
-            Statement::If => self.if_stmt(stmt),
-            Statement::Goto => self.goto_stmt(stmt),
-            Statement::Let => self.let_stmt(stmt),
-            Statement::Gosub => self.gosub_stmt(stmt),
-            Statement::Return => self.return_stmt(),
-            Statement::End => self.end_stmt(),
-            Statement::Input => self.input_stmt(stmt),
-        }.or_else(|error| {
-            match error.get_kind() {
-                TinyBasicErrorKind::ExecutionReachedEnd => Ok(()),
-                _ => Err(error)
-            }
-        }.and_then(|_| stmt
-            .is_empty()
-            .then_some(())
-            .ok_or(TinyBasicError::from_context(stmt, TinyBasicErrorKind::UnexpectedTokensAtEndOfLine, self.current_line_number))))
+    pub(crate) fn pause(&mut self, state: PausedState, program: &ProgramStorage) {
+        self.paused = Some(Paused { state, revision: program.revision() });
+    }
 
+    /// Takes the paused program unless the program was edited since
+    pub(crate) fn take_paused(&mut self, program: &ProgramStorage) -> tiny_basic::Result<'line_source, PausedState> {
+        match self.paused.take_if(|paused| paused.revision == program.revision()) {
+            Some(paused) => Ok(paused.state),
+            None => Err(TinyBasicError::from(TinyBasicErrorKind::CantContinue)),
+        }
     }
 
-    fn print_stmt(&mut self, expr_list: &mut AsciiCharStream<'line_source>) -> tiny_basic::Result<'line_source, ()> {
-        if let Some(string) = expr_list.consume_string()? {
-            print!("{} ", string);
-        } else {
-            let expr_value = self.expression(expr_list)?;
-            print!("{} ", expr_value);
+    /// Lowers the interrupt flag, returning whether it was raised
+    pub(crate) fn take_interrupt(&self) -> bool {
+        interrupt::take(self.interrupt)
+    }
+
+    /// Returns the value of a variable, or `None` if it has never been assigned
+    pub fn get_variable(&self, name: &str) -> Option<&types::Value> {
+        let name = name.as_ascii_str().ok()?;
+        self.environment.get(name)
+    }
+
+    pub fn variables(&self) -> impl Iterator<Item = (&AsciiStr, &types::Value)> {
+        self.environment
+            .iter()
+            .map(|(name, value)| (name.as_ref(), value))
+    }
+
+    /// Executes a line of statements separated by colons in the immediate mode.
+    ///
+    /// GOTO and GOSUB run the program from their target line, keeping the variables.
+    /// Once the subroutine returns, the rest of the line is executed.
+    pub fn execute(&mut self, line: &'line_source AsciiStr, program: &'line_source ProgramStorage) -> tiny_basic::Result<'line_source, ()> {
+        let statements = parser::parse_line(line, None)?;
+        let context = AsciiCharStream::from_ascii_str(line);
+        let return_depth = self.return_stack.len();
+        let mut start = 0;
+        loop {
+            self.next_line_to_execute = None;
+            self.immediate_return = None;
+            match self.execute_statements(&statements, start) {
+                Err(error) if matches!(error.get_kind(), TinyBasicErrorKind::ExecutionReachedEnd) => return Ok(()),
+                result => result.map_err(|error| error.set_context(&context))?,
+            }
+            if self.next_line_to_execute.is_none() {
+                return Ok(());
+            }
+
+            self.paused = None;
+            self.take_interrupt();
+            let result = self.run_lines(program);
+            self.current_line_number = None;
+            // Unless RETURN came back to this line, its GOSUB frames must not outlive it
+            if result.is_err() || self.immediate_return.is_none() {
+                self.return_stack.truncate(return_depth);
+            }
+            result?;
+            match self.immediate_return {
+                Some(next) => start = next,
+                None => return Ok(()),
+            }
         }
+    }
 
-        while expr_list.consume_char(ascii::AsciiChar::Comma).is_some() {
-            if let Some(string) = expr_list.consume_string()? {
-                print!("{} ", string);
-            } else {
-                let expr_value = self.expression(expr_list)?;
-                print!("{} ", expr_value);
+    /// Executes the statements of a line starting from the one at `start`
+    fn execute_statements(&mut self, statements: &[ast::Statement], start: usize) -> tiny_basic::Result<'line_source, ()> {
+        self.control_transferred = false;
+        for (i, statement) in statements.iter().enumerate().skip(start) {
+            self.current_statement = i;
+            self.execute_statement(statement, i + 1)?;
+            if self.control_transferred {
+                break;
             }
         }
+        Ok(())
+    }
+
+    /// Executes a statement, `next` being the position of the following statement in the line
+    fn execute_statement(&mut self, statement: &ast::Statement, next: usize) -> tiny_basic::Result<'line_source, ()> {
+        match &statement.kind {
+            StatementKind::Print(expressions) => self.print_stmt(expressions),
+            StatementKind::If { lhs, operator, rhs } => self.if_stmt(lhs, *operator, rhs),
+            StatementKind::Goto(line) => self.goto_stmt(line),
+            StatementKind::Let(target, value) => self.let_stmt(target, value),
+            StatementKind::Gosub(line) => self.gosub_stmt(line, next),
+            StatementKind::Return => self.return_stmt(),
+            StatementKind::End => self.end_stmt(),
+            StatementKind::Input(targets) => self.input_stmt(targets),
+            StatementKind::For(for_loop) => self.for_stmt(for_loop, statement.end, next),
+            StatementKind::Next(variable) => self.next_stmt(variable.as_deref(), statement.end),
+            StatementKind::Dim(arrays) => self.dim_stmt(arrays),
+            StatementKind::Randomize(seed) => self.randomize_stmt(seed.as_ref()),
+            StatementKind::Stop => Err(TinyBasicError::from(TinyBasicErrorKind::Break)),
+            StatementKind::Rem => Ok(()),
+        }
+    }
+
+    pub(crate) fn error(&self, location: usize, kind: TinyBasicErrorKind) -> TinyBasicError<'line_source> {
+        TinyBasicError::at_location(location, kind, self.current_line_number)
+    }
+
+    fn print_stmt(&mut self, expressions: &[ast::Expression]) -> tiny_basic::Result<'line_source, ()> {
+        let values = expressions
+            .iter()
+            .map(|expression| self.expression(expression))
+            .collect::<tiny_basic::Result<'line_source, Vec<_>>>()?;
+        self.print(&values)
+    }
+
+    pub(crate) fn print(&mut self, values: &[types::Value]) -> tiny_basic::Result<'line_source, ()> {
+        let mut output = String::new();
+        for value in values {
+            output += &format!("{} ", value);
+        }
+
+        output.push('\n');
+        self.write(&output)
+    }
 
-        println!();
+    fn if_stmt(&mut self, lhs: &ast::Expression, relop: RelationalOperator, rhs: &ast::Expression) -> tiny_basic::Result<'line_source, ()> {
+        let lhs_value = self.expression(lhs)?;
+        let rhs_value = self.expression(rhs)?;
 
+        // The statements after THEN are the rest of the line, which is skipped as after a jump
+        if !self.compare(lhs_value, relop, rhs_value, rhs.location)? {
+            self.control_transferred = true;
+        }
         Ok(())
     }
 
-    fn if_stmt(&mut self, stmt: &mut AsciiCharStream<'line_source>) -> tiny_basic::Result<'line_source, ()> {
-        let lhs = self.expression(stmt)?;
-        let relop = stmt
-            .consume_relop()
-            .ok_or(TinyBasicError::from_context(stmt, TinyBasicErrorKind::ExpectedRelationalOperator, self.current_line_number))?;
-        let rhs = self.expression(stmt)?;
-
-        let condition = match relop {
-            super::char_stream::RelationalOperator::Less => lhs < rhs,
-            super::char_stream::RelationalOperator::Greater => lhs > rhs,
-            super::char_stream::RelationalOperator::LessEqual => lhs <= rhs,
-            super::char_stream::RelationalOperator::GreaterEqual => lhs >= rhs,
-            super::char_stream::RelationalOperator::NotEqual => lhs != rhs,
-            super::char_stream::RelationalOperator::Equal => lhs == rhs,
+    /// Evaluates the condition of an IF
+    pub(crate) fn compare(
+        &self,
+        lhs: types::Value,
+        relop: RelationalOperator,
+        rhs: types::Value,
+        rhs_location: usize
+    ) -> tiny_basic::Result<'line_source, bool> {
+        // Numbers are compared with numbers and strings with strings
+        let ordering = match (&lhs, &rhs) {
+            (types::Value::Number(lhs), types::Value::Number(rhs)) => lhs.partial_cmp(rhs).expect("Numbers should be comparable"),
+            (types::Value::String(lhs), types::Value::String(rhs)) => lhs.cmp(rhs),
+            _ => return Err(self.error(rhs_location, TinyBasicErrorKind::TypeMismatch)),
         };
 
-        if condition {
-            stmt
-                .consume_keyword()
-                .and_then(|keyword| {
-                    match keyword {
-                        Keyword::Then => Some(()),
-                        _ => None
-                    }
-                })
-                .ok_or(TinyBasicError::from_context(stmt, TinyBasicErrorKind::ExpectedKeyword, self.current_line_number))?;
-            self.execute(stmt)
-        } else {
-            stmt.flush();
-            Ok(())
-        }
+        Ok(match relop {
+            RelationalOperator::Less => ordering.is_lt(),
+            RelationalOperator::Greater => ordering.is_gt(),
+            RelationalOperator::LessEqual => ordering.is_le(),
+            RelationalOperator::GreaterEqual => ordering.is_ge(),
+            RelationalOperator::NotEqual => ordering.is_ne(),
+            RelationalOperator::Equal => ordering.is_eq(),
+        })
     }
 
-    fn goto_stmt(&mut self, stmt: &mut AsciiCharStream<'line_source>) -> tiny_basic::Result<'line_source, ()> {
-        let next_line_index: types::LineIndex = self.expression(stmt)?.try_into()?;
-        self.next_line_to_execute = Some(next_line_index);
+    fn goto_stmt(&mut self, line: &ast::Expression) -> tiny_basic::Result<'line_source, ()> {
+        let next_line_index = types::LineIndex::from_number(self.numeric_expression(line)?)?;
+        self.jump_to(next_line_index, 0);
         Ok(())
     }
 
-    fn let_stmt(&mut self, stmt: &mut AsciiCharStream<'line_source>) -> tiny_basic::Result<'line_source, ()> {
-        let var_name = 
-            stmt
-            .consume_var();
+    fn let_stmt(&mut self, target: &ast::Target, value: &ast::Expression) -> tiny_basic::Result<'line_source, ()> {
+        let target = self.target(target)?;
+        let value_result = self.expression(value)?;
+        self.assign_checked(target, value_result, value.location)
+    }
 
-        if var_name.is_none() {
-            return Err(TinyBasicError::from_context(stmt, TinyBasicErrorKind::ExpectedVariableName, self.current_line_number));
+    /// Assigns a value of LET, which must be of the same type as the target
+    pub(crate) fn assign_checked(&mut self, target: Target, value: types::Value, value_location: usize) -> tiny_basic::Result<'line_source, ()> {
+        if value.is_string() != target.is_string() {
+            return Err(self.error(value_location, TinyBasicErrorKind::TypeMismatch));
         }
-        let var_name = var_name.unwrap().to_owned();
+        self.assign(target, value);
+        Ok(())
+    }
+
+    /// DIM name(bound [, bound]) [, ...]
+    fn dim_stmt(&mut self, arrays: &[ast::ArrayDeclaration]) -> tiny_basic::Result<'line_source, ()> {
+        for declaration in arrays {
+            let bounds = self.expression_list(&declaration.bounds)?;
+            self.dim(&declaration.name, declaration.location, &bounds)?;
+        }
+        Ok(())
+    }
 
-        stmt
-            .consume_char(AsciiChar::Equal)
-            .ok_or(TinyBasicError::from_context(stmt, TinyBasicErrorKind::Expected('='), self.current_line_number))?;
-        let value = self.expression(stmt)?;
-        self.environment.insert(var_name, value);
+    pub(crate) fn dim(&mut self, name: &AsciiStr, location: usize, bounds: &[types::Number]) -> tiny_basic::Result<'line_source, ()> {
+        if self.arrays.contains_key(name) {
+            return Err(self.error(location, TinyBasicErrorKind::ArrayAlreadyDimensioned));
+        }
+        let array = Array::new(bounds, types::Value::default_for(name))
+            .map_err(|kind| self.error(location, kind))?;
+        self.arrays.insert(name.to_owned(), array);
         Ok(())
     }
 
-    fn gosub_stmt(&mut self, stmt: &mut AsciiCharStream<'line_source>) -> tiny_basic::Result<'line_source, ()> {
-        let subroutine_address: types::LineIndex = self.expression(stmt)?.try_into()?;
-        let return_address = 
-            self.next_line_to_execute
-            .ok_or(TinyBasicError::from_context(stmt, TinyBasicErrorKind::CommandNotUsableInInteractiveMode, self.current_line_number))?;
+    /// Finds the variable or the array element to assign to
+    fn target(&self, target: &ast::Target) -> tiny_basic::Result<'line_source, Target> {
+        match &target.subscripts {
+            Some(subscripts) => {
+                let values = self.expression_list(subscripts)?;
+                let offset = self.element_offset(&target.name, target.location, &values, |i| subscripts[i].location)?;
+                Ok(Target::Element(target.name.clone(), offset))
+            },
+            None => Ok(Target::Variable(target.name.clone())),
+        }
+    }
+
+    pub(crate) fn assign(&mut self, target: Target, value: types::Value) {
+        match target {
+            Target::Variable(name) => {
+                self.environment.insert(name, value);
+            },
+            Target::Element(name, offset) => {
+                self.arrays
+                    .get_mut(&name)
+                    .expect("Array should exist since its element was found")
+                    .set(offset, value);
+            },
+        }
+    }
+
+    /// Evaluates a list of numeric expressions, e.g. subscripts or function arguments
+    fn expression_list(&self, expressions: &[ast::Expression]) -> tiny_basic::Result<'line_source, Vec<types::Number>> {
+        expressions
+            .iter()
+            .map(|expression| self.numeric_expression(expression))
+            .collect()
+    }
+
+    /// Finds an element of an array, `subscript_location` giving the location of a subscript by its position
+    pub(crate) fn element_offset(
+        &self,
+        name: &AsciiStr,
+        name_location: usize,
+        subscripts: &[types::Number],
+        subscript_location: impl Fn(usize) -> usize
+    ) -> tiny_basic::Result<'line_source, usize> {
+        let array = self
+            .arrays
+            .get(name)
+            .ok_or(self.error(name_location, TinyBasicErrorKind::UndefinedArray))?;
+        if subscripts.len() != array.dimensions() {
+            return Err(self.error(name_location, TinyBasicErrorKind::WrongNumberOfSubscripts));
+        }
+
+        array
+            .offset(subscripts)
+            .map_err(|i| self.error(subscript_location(i), TinyBasicErrorKind::SubscriptOutOfRange))
+    }
+
+    pub(crate) fn is_array(&self, name: &AsciiStr) -> bool {
+        self.arrays.contains_key(name)
+    }
+
+    pub(crate) fn element(&self, name: &AsciiStr, offset: usize) -> types::Value {
+        self.arrays[name].get(offset).clone()
+    }
+
+    /// Value of a variable, a variable which was never assigned being zero or an empty string
+    pub(crate) fn variable(&self, name: &AsciiStr) -> types::Value {
+        self.environment
+            .get(name)
+            .cloned()
+            .unwrap_or_else(|| types::Value::default_for(name))
+    }
+
+    fn gosub_stmt(&mut self, line: &ast::Expression, next: usize) -> tiny_basic::Result<'line_source, ()> {
+        let subroutine_address = types::LineIndex::from_number(self.numeric_expression(line)?)?;
+        let return_address = match self.current_line_number {
+            Some(return_line) => ReturnAddress::Statement(StatementAddress {
+                line: return_line,
+                offset: next
+            }),
+            None => ReturnAddress::Immediate(next),
+        };
 
         self.return_stack.push(return_address);
-        self.next_line_to_execute = Some(subroutine_address);
+        self.jump_to(subroutine_address, 0);
         Ok(())
     }
 
@@ -195,45 +549,181 @@ impl<'line_source> Interpreter {
             .return_stack
             .pop()
             .ok_or(TinyBasicError::from( TinyBasicErrorKind::ReturnOnEmptyStack))?;
-        self.next_line_to_execute = Some(return_address);
+        match return_address {
+            ReturnAddress::Statement(address) => self.jump_to(address.line, address.offset),
+            // The program stops, and the line typed at the prompt goes on
+            ReturnAddress::Immediate(offset) => {
+                self.next_line_to_execute = None;
+                self.immediate_return = Some(offset);
+                self.control_transferred = true;
+            },
+        }
         Ok(())
     }
 
-    fn input_stmt(&mut self, var_list: &mut AsciiCharStream<'line_source>) -> tiny_basic::Result<'line_source, ()> {
-        self.input_var(var_list)?;
-        while var_list.consume_char(AsciiChar::Comma).is_some() {
-            self.input_var(var_list)?;
+    fn jump_to(&mut self, line: types::LineIndex, offset: usize) {
+        self.next_line_to_execute = Some(line);
+        self.next_statement_offset = offset;
+        self.control_transferred = true;
+    }
+
+    /// FOR var = start TO limit [STEP step]
+    ///
+    /// As in most of the 8-bit BASICs, the body is executed at least once
+    /// since the limit is only checked by NEXT.
+    fn for_stmt(&mut self, for_loop: &ast::ForLoop, end: usize, next: usize) -> tiny_basic::Result<'line_source, ()> {
+        if types::Value::is_string_name(&for_loop.variable) {
+            return Err(self.error(for_loop.variable_location, TinyBasicErrorKind::TypeMismatch));
         }
+        let start = self.numeric_expression(&for_loop.start)?;
+        let limit = self.numeric_expression(&for_loop.limit)?;
+        let step = match &for_loop.step {
+            Some(step) => self.numeric_expression(step)?,
+            None => types::Number::one(),
+        };
+
+        let line = self
+            .current_line_number
+            .ok_or(self.error(end, TinyBasicErrorKind::CommandNotUsableInInteractiveMode))?;
+
+        let mut loop_stack = std::mem::take(&mut self.loop_stack);
+        self.enter_loop(&mut loop_stack, &for_loop.variable, start, limit, step, StatementAddress { line, offset: next });
+        self.loop_stack = loop_stack;
         Ok(())
     }
 
-    fn input_var(&mut self,  var_list: &mut AsciiCharStream<'line_source>) -> tiny_basic::Result<'line_source, ()> {
-        let var_name = var_list.consume_var();
+    pub(crate) fn enter_loop<A>(
+        &mut self,
+        loop_stack: &mut Vec<LoopFrame<A>>,
+        variable: &AsciiStr,
+        start: types::Number,
+        limit: types::Number,
+        step: types::Number,
+        body: A
+    ) {
+        // Restarting a loop discards it together with all of the loops nested in it
+        if let Some(i) = loop_stack.iter().position(|frame| *frame.variable == *variable) {
+            loop_stack.truncate(i);
+        }
+
+        self.environment.insert(variable.to_owned(), start.into());
+        loop_stack.push(LoopFrame {
+            variable: variable.to_owned(),
+            limit,
+            step,
+            body
+        });
+    }
+
+    /// NEXT [var]
+    fn next_stmt(&mut self, variable: Option<&AsciiStr>, end: usize) -> tiny_basic::Result<'line_source, ()> {
+        let mut loop_stack = std::mem::take(&mut self.loop_stack);
+        let body = self.next_iteration(&mut loop_stack, variable, end);
+        self.loop_stack = loop_stack;
+        if let Some(body) = body? {
+            self.jump_to(body.line, body.offset);
+        }
+        Ok(())
+    }
 
-        if var_name.is_none() {
-            return Err(TinyBasicError::from_context(var_list, TinyBasicErrorKind::ExpectedVariableName, self.current_line_number));
+    /// Steps the loop continued by NEXT, returning its body if it is to be executed again
+    pub(crate) fn next_iteration<A: Copy>(
+        &mut self,
+        loop_stack: &mut Vec<LoopFrame<A>>,
+        variable: Option<&AsciiStr>,
+        end: usize
+    ) -> tiny_basic::Result<'line_source, Option<A>> {
+        let frame_index = match variable {
+            Some(variable) => loop_stack.iter().rposition(|frame| *frame.variable == *variable),
+            None => loop_stack.len().checked_sub(1),
         }
-        let var_name = var_name.unwrap();
+        .ok_or(self.error(end, TinyBasicErrorKind::NextWithoutFor))?;
+        // Loops nested in the one being continued are left without their NEXT
+        loop_stack.truncate(frame_index + 1);
+
+        let frame = &loop_stack[frame_index];
+        let current = match self.environment.get(&frame.variable) {
+            Some(types::Value::Number(value)) => value.to_owned(),
+            _ => types::Number::zero(),
+        };
+        let value = self.arithmetic
+            .add(current, frame.step.to_owned())
+            .map_err(|kind| self.error(end, kind))?;
 
-        print!("{}? ", var_name);
-        stdout().flush();
-        let user_input = Self::get_user_input()?;
-        if let Some(number) = user_input.as_str().parse::<types::Number>().ok() {
-            self.environment.insert(var_name.to_owned(), number);
+        let continue_loop = if frame.step >= types::Number::zero() {
+            value <= frame.limit
         } else {
-            let first_char_code = *user_input.as_bytes().iter().nth(0).expect("User input should not be empty");
-            self.environment.insert(var_name.to_owned(), first_char_code as types::Number);
+            value >= frame.limit
+        };
+
+        if continue_loop {
+            self.environment.insert(frame.variable.clone(), value.into());
+            Ok(Some(frame.body))
+        } else {
+            let frame = loop_stack.pop().expect("Loop stack should not be empty");
+            self.environment.insert(frame.variable, value.into());
+            Ok(None)
         }
+    }
+
+    /// RANDOMIZE [seed]
+    ///
+    /// Without the seed, the generator is seeded from the current time.
+    fn randomize_stmt(&mut self, seed: Option<&ast::Expression>) -> tiny_basic::Result<'line_source, ()> {
+        let seed = match seed {
+            Some(seed) => Some((self.numeric_expression(seed)?, seed.location)),
+            None => None,
+        };
+        self.randomize(seed)
+    }
+
+    /// Seeds the generator with a number found at a location, or from the time
+    pub(crate) fn randomize(&mut self, seed: Option<(types::Number, usize)>) -> tiny_basic::Result<'line_source, ()> {
+        let seed = match seed {
+            None => std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|time| time.as_nanos() as u64)
+                .unwrap_or(Prng::DEFAULT_SEED),
+            // Negative seeds are as good as positive ones
+            Some((seed, location)) => seed
+                .to_i64()
+                .ok_or(self.error(location, TinyBasicErrorKind::InvalidArgument))?
+                as u64,
+        };
+        self.random.seed(seed);
         Ok(())
-    } 
+    }
 
-    fn get_user_input() -> tiny_basic::Result<'line_source, AsciiString> {
-        let mut user_input = String::new();
-        while let Ok(read_bytes) = stdin().read_line(&mut user_input) {
-            if read_bytes > 0 {
-                break;
-            }
+    /// INPUT var [, var], where each target is prompted for with its text
+    fn input_stmt(&mut self, targets: &[(ast::Target, AsciiString)]) -> tiny_basic::Result<'line_source, ()> {
+        for (target, target_text) in targets {
+            let target = self.target(target)?;
+            self.input(target, target_text)?;
         }
+        Ok(())
+    }
+
+    pub(crate) fn input(&mut self, target: Target, target_text: &AsciiStr) -> tiny_basic::Result<'line_source, ()> {
+        self.write(&format!("{}? ", target_text))?;
+        let user_input = self.get_user_input()?;
+        if target.is_string() {
+            self.assign(target, types::Value::String(user_input));
+        } else if let Ok(number) = types::Number::parse_literal(user_input.as_str()) {
+            self.assign(target, number.into());
+        } else {
+            let first_char_code = user_input.as_bytes().first().copied().unwrap_or(0);
+            let first_char_code = types::Number::from_i64(i64::from(first_char_code)).expect("Character code should fit into any number");
+            self.assign(target, first_char_code.into());
+        }
+        Ok(())
+    } 
+
+    pub(crate) fn get_user_input(&mut self) -> tiny_basic::Result<'line_source, AsciiString> {
+        let user_input = self
+            .console
+            .read_line()
+            .map_err(TinyBasicErrorKind::from)?
+            .ok_or(TinyBasicErrorKind::from(std::io::Error::from(std::io::ErrorKind::UnexpectedEof)))?;
         let user_input = user_input
             .trim()
             .as_ascii_str()
@@ -241,85 +731,634 @@ impl<'line_source> Interpreter {
         Ok(user_input.to_owned())
     }
 
+    pub(crate) fn write(&mut self, text: &str) -> tiny_basic::Result<'line_source, ()> {
+        self.console
+            .write(text)
+            .and_then(|_| self.console.flush())
+            .map_err(|error| TinyBasicError::from(TinyBasicErrorKind::from(error)))
+    }
+
     fn end_stmt(&mut self) -> tiny_basic::Result<'line_source, ()> {
+        self.next_line_to_execute = None;
+        self.control_transferred = true;
         Err(TinyBasicError::from(TinyBasicErrorKind::ExecutionReachedEnd))
     }
 
-    fn expression(&self, stmt: &mut AsciiCharStream<'line_source>) -> tiny_basic::Result<'line_source, types::Number> {
-        let sign = stmt.consume_char_if(is_plus_or_minus);
-        let sign: types::Number = match sign {
-            Some(sign) => {
-                get_sign_value(sign)
+    /// Evaluates an expression which is required to be a number
+    fn numeric_expression(&self, expression: &ast::Expression) -> tiny_basic::Result<'line_source, types::Number> {
+        let value = self.expression(expression)?;
+        self.number_operand(value, expression.location)
+    }
+
+    pub(crate) fn number_operand(&self, value: types::Value, location: usize) -> tiny_basic::Result<'line_source, types::Number> {
+        match value {
+            types::Value::Number(number) => Ok(number),
+            types::Value::String(_) => Err(self.error(location, TinyBasicErrorKind::TypeMismatch)),
+        }
+    }
+
+    fn expression(&self, expression: &ast::Expression) -> tiny_basic::Result<'line_source, types::Value> {
+        match &expression.kind {
+            ExpressionKind::Number(number) => Ok(number.to_owned().into()),
+            ExpressionKind::String(string) => Ok(types::Value::String(string.clone())),
+            ExpressionKind::Variable(name) => Ok(self.variable(name)),
+            ExpressionKind::Call(name, arguments) => {
+                if self.is_array(name) {
+                    let subscripts = self.expression_list(arguments)?;
+                    let offset = self.element_offset(name, expression.location, &subscripts, |i| arguments[i].location)?;
+                    return Ok(self.element(name, offset));
+                }
+                let function = self.function(name, expression.location)?;
+                let arguments = self.expression_list(arguments)?;
+                self.call_function(function, expression.location, &arguments)
+            },
+            ExpressionKind::Unary(operator, operand) => {
+                let value = self.expression(operand)?;
+                self.apply_unary_operator(*operator, value, operand.location, expression.location)
+            },
+            ExpressionKind::Binary { operator, operator_location, lhs, rhs } => {
+                let lhs_value = self.expression(lhs)?;
+                let rhs_value = self.expression(rhs)?;
+                self.apply_operator(*operator, lhs_value, rhs_value, *operator_location, rhs.location)
             },
-            None => 1,
-        };
-        
-        let mut total_term = sign * self.term(stmt)?;
-        while let Some(sign) = stmt.consume_char_if(is_plus_or_minus) {
-            let sign = get_sign_value(sign);
-            let other = self.term(stmt)?;
-            total_term += sign * other;
-        }
-        Ok(total_term)
-    }
-
-    fn term(&self, stmt: &mut AsciiCharStream<'line_source>) -> tiny_basic::Result<'line_source, types::Number> {
-        let mut total_factor = self.factor(stmt)?;
-        if let Some(op) = stmt.consume_char_if(is_slash_or_asterisk) {
-            let other = self.factor(stmt)?;
-            match op {
-                ascii::AsciiChar::Slash => total_factor /= other,
-                ascii::AsciiChar::Asterisk => total_factor *= other,
-                _ => return Err(TinyBasicError::from_context(stmt, TinyBasicErrorKind::UnexpectedOperator, self.current_line_number)),
-            }
         }
-        Ok(total_factor)
-    }
-
-    fn factor(&self, stmt: &mut AsciiCharStream<'line_source>) -> tiny_basic::Result<'line_source, types::Number>  {
-        if let Some(var_name) = stmt.consume_var() {
-            Ok(self.environment
-                .get(var_name)
-                .cloned()
-                .unwrap_or(0))
-        } else if let Some(number) = stmt.consume_number() {
-            let number: types::Number = 
-                number
-                .as_str()
-                .parse()
-                .map_err(|error| TinyBasicError::from_context(stmt, TinyBasicErrorKind::from(error), self.current_line_number))?;
-            Ok(number)
-        } else if stmt.consume_char(AsciiChar::ParenOpen).is_some() {
-            let expr_value = self.expression(stmt)?;
-            stmt
-                .consume_char(AsciiChar::ParenClose)
-                .ok_or(TinyBasicError::from_context(stmt, TinyBasicErrorKind::Expected(')'), self.current_line_number))?;
-            Ok(expr_value)
-        } else {
-            Err(TinyBasicError::from_context(stmt, TinyBasicErrorKind::FactorCouldNotBeParsed, self.current_line_number))
+    }
+
+    /// Applies the unary plus or minus found at `location`
+    pub(crate) fn apply_unary_operator(
+        &self,
+        operator: ArithmeticOperator,
+        value: types::Value,
+        operand_location: usize,
+        location: usize
+    ) -> tiny_basic::Result<'line_source, types::Value> {
+        let value = self.number_operand(value, operand_location)?;
+        match operator {
+            ArithmeticOperator::Subtract => self
+                .arithmetic
+                .neg(value)
+                .map(types::Value::from)
+                .map_err(|kind| self.error(location, kind)),
+            _ => Ok(value.into()),
         }
     }
-}
 
-fn is_plus_or_minus(ch: &AsciiChar) -> bool {
-    match *ch {
-        ascii::AsciiChar::Plus | ascii::AsciiChar::Minus => true,
-        _ => false
+    pub(crate) fn apply_operator(
+        &self,
+        operator: ArithmeticOperator,
+        lhs: types::Value,
+        rhs: types::Value,
+        operator_location: usize,
+        rhs_location: usize
+    ) -> tiny_basic::Result<'line_source, types::Value> {
+        match (operator, lhs, rhs) {
+            (operator, types::Value::Number(lhs), types::Value::Number(rhs)) => {
+                let result = match operator {
+                    ArithmeticOperator::Add => self.arithmetic.add(lhs, rhs),
+                    ArithmeticOperator::Subtract => self.arithmetic.sub(lhs, rhs),
+                    ArithmeticOperator::Multiply => self.arithmetic.mul(lhs, rhs),
+                    ArithmeticOperator::Divide => self.arithmetic.div(lhs, rhs),
+                };
+                result
+                    .map(types::Value::from)
+                    .map_err(|kind| {
+                        // Division by zero is blamed on the divisor, overflow on the operation
+                        let location = match kind {
+                            TinyBasicErrorKind::DivisionByZero => rhs_location,
+                            _ => operator_location,
+                        };
+                        self.error(location, kind)
+                    })
+            },
+            (ArithmeticOperator::Add, types::Value::String(mut lhs), types::Value::String(rhs)) => {
+                lhs.push_str(&rhs);
+                Ok(types::Value::String(lhs))
+            },
+            _ => Err(self.error(rhs_location, TinyBasicErrorKind::TypeMismatch)),
+        }
+    }
+
+    /// Finds the function called at `location`, before its arguments are evaluated
+    pub(crate) fn function(&self, name: &AsciiStr, location: usize) -> tiny_basic::Result<'line_source, &'static functions::Function> {
+        functions::find(name)
+            .ok_or(self.error(location, TinyBasicErrorKind::UnknownFunction))
     }
-}
 
-fn is_slash_or_asterisk(ch: &AsciiChar) -> bool {
-    match *ch {
-        ascii::AsciiChar::Slash | ascii::AsciiChar::Asterisk => true,
-        _ => false
+    pub(crate) fn call_function(
+        &self,
+        function: &functions::Function,
+        location: usize,
+        arguments: &[types::Number]
+    ) -> tiny_basic::Result<'line_source, types::Value> {
+        if !function.accepts(arguments.len()) {
+            return Err(self.error(location, TinyBasicErrorKind::WrongNumberOfArguments));
+        }
+        function
+            .call(arguments, &self.random, self.arithmetic)
+            .map(types::Value::from)
+            .map_err(|kind| self.error(location, kind))
     }
 }
 
-fn get_sign_value(ch: AsciiChar) -> types::Number {
-    assert!(is_plus_or_minus(&ch));
-    match ch {
-        ascii::AsciiChar::Plus => 1,
-        ascii::AsciiChar::Minus => -1,
-        _ => unreachable!()
+#[cfg(test)]
+mod tests {
+    use crate::tiny_basic::{console::BufferConsole, error::ErrorKind, program_storage::ProgramStorage, types::Value};
+    use crate::tiny_basic::engine::run_differential;
+    use crate::tiny_basic::syntax_check::check_line;
+    use crate::tiny_basic::types::ArithmeticMode;
+
+    use super::Interpreter;
+
+    #[test]
+    fn test_run_and_inspect_variables() {
+        let program = ProgramStorage::load("10 LET A = 2\n20 LET B = A * 21\n30 END\n40 LET B = 0").unwrap();
+        let mut interpreter = Interpreter::with_console(BufferConsole::new());
+        interpreter.run(&program).unwrap();
+        assert_eq!(interpreter.get_variable("A").map(Value::to_string).as_deref(), Some("2"));
+        assert_eq!(interpreter.get_variable("B").map(Value::to_string).as_deref(), Some("42"));
+        assert_eq!(interpreter.get_variable("C"), None);
+        assert_eq!(interpreter.variables().count(), 2);
+    }
+
+    #[test]
+    fn test_runtime_error_has_line_number() {
+        let program = ProgramStorage::load("10 LET A = 1\n20 RETURN").unwrap();
+        let error = Interpreter::with_console(BufferConsole::new()).run(&program).err().unwrap();
+        assert!(matches!(error.get_kind(), ErrorKind::ReturnOnEmptyStack));
+        assert_eq!(error.get_line_number().map(Into::into), Some(20));
+        assert!(error.to_string().contains("RETURN"));
+    }
+
+    #[test]
+    fn test_print_to_console() {
+        let program = ProgramStorage::load("10 PRINT \"A =\", 2 + 3\n20 PRINT 7").unwrap();
+        let mut interpreter = Interpreter::with_console(BufferConsole::new());
+        interpreter.run(&program).unwrap();
+        assert_eq!(interpreter.console().output(), "A = 5 \n7 \n");
+    }
+
+    #[test]
+    fn test_input_from_console() {
+        let program = ProgramStorage::load("10 INPUT A, B\n20 PRINT A + B").unwrap();
+        let mut interpreter = Interpreter::with_console(BufferConsole::with_input("40\n2\n"));
+        interpreter.run(&program).unwrap();
+        assert_eq!(interpreter.console().output(), "A? B? 42 \n");
+    }
+
+    #[test]
+    fn test_input_at_end_of_input() {
+        let program = ProgramStorage::load("10 INPUT A").unwrap();
+        let error = Interpreter::with_console(BufferConsole::new()).run(&program).err().unwrap();
+        assert!(matches!(error.get_kind(), ErrorKind::IoError(std::io::ErrorKind::UnexpectedEof)));
+    }
+
+    /// Runs a program with every engine, checking that they produce the same output and errors
+    fn assert_engines_agree(program: &ProgramStorage) {
+        if let Err(outcomes) = run_differential(program, "", ArithmeticMode::default()) {
+            panic!("Engines disagree: {:#?}", outcomes);
+        }
+    }
+
+    /// Runs a correct program, which is expected to pass the syntax check as well
+    fn run_program(source: &str) -> String {
+        let program = ProgramStorage::load(source).unwrap();
+        for (i, line) in program.iter() {
+            assert!(check_line(line, Some(*i)).is_ok(), "{} {}", i, line);
+        }
+        assert_engines_agree(&program);
+        let mut interpreter = Interpreter::with_console(BufferConsole::new());
+        interpreter.run(&program).unwrap();
+        interpreter.console_mut().take_output()
+    }
+
+    #[test]
+    fn test_multiple_statements_per_line() {
+        assert_eq!(run_program("10 LET A = 1 : PRINT A : GOTO 30\n20 PRINT 20\n30 PRINT 30"), "1 \n30 \n");
+        assert_eq!(run_program("10 IF 1 = 1 THEN PRINT 1 : PRINT 2\n20 IF 1 = 0 THEN PRINT 3 : PRINT 4"), "1 \n2 \n");
+        assert_eq!(run_program("10 IF 1 = 1 THEN GOTO 30 : PRINT 1\n20 PRINT 20\n30 PRINT 30"), "30 \n");
+        assert_eq!(run_program("10 END : PRINT 1"), "");
+    }
+
+    #[test]
+    fn test_return_to_middle_of_line() {
+        let source = "10 GOSUB 100 : PRINT 2 : GOSUB 100\n20 PRINT 3\n30 END\n100 PRINT 1 : RETURN : PRINT 0";
+        assert_eq!(run_program(source), "1 \n2 \n1 \n3 \n");
+    }
+
+    #[test]
+    fn test_immediate_multiple_statements() {
+        let mut interpreter = Interpreter::with_console(BufferConsole::new());
+        let program = ProgramStorage::new();
+        let line = ascii::AsciiStr::from_ascii("LET A = 2 : PRINT A * 3").unwrap();
+        interpreter.execute(line, &program).unwrap();
+        assert_eq!(interpreter.console().output(), "6 \n");
+    }
+
+    #[test]
+    fn test_immediate_goto_and_gosub() {
+        let mut interpreter = Interpreter::with_console(BufferConsole::new());
+        let program = ProgramStorage::load("10 PRINT 10\n20 PRINT A\n30 END\n100 LET A = A + 1 : RETURN").unwrap();
+
+        // GOTO keeps the variables, unlike RUN
+        let line = ascii::AsciiStr::from_ascii("LET A = 7 : GOTO 20 : PRINT 0").unwrap();
+        interpreter.execute(line, &program).unwrap();
+        assert_eq!(interpreter.console_mut().take_output(), "7 \n");
+
+        let line = ascii::AsciiStr::from_ascii("GOSUB 100 : PRINT A : GOSUB 100 : PRINT A").unwrap();
+        interpreter.execute(line, &program).unwrap();
+        assert_eq!(interpreter.console_mut().take_output(), "8 \n9 \n");
+
+        // A subroutine which ends the program does not come back to the line
+        let line = ascii::AsciiStr::from_ascii("GOSUB 20 : PRINT 0").unwrap();
+        interpreter.execute(line, &program).unwrap();
+        assert_eq!(interpreter.console_mut().take_output(), "9 \n");
+    }
+
+    #[test]
+    fn test_immediate_gosub_frame_is_dropped() {
+        for subroutine in ["20 END", "20 STOP", "20 PRINT 1 / 0"] {
+            let source = format!("{}\n100 LET A = A + 1 : RETURN", subroutine);
+            let program = ProgramStorage::load(&source).unwrap();
+            let mut interpreter = Interpreter::with_console(BufferConsole::new());
+            let line = ascii::AsciiStr::from_ascii("GOSUB 20 : PRINT 0").unwrap();
+            let _ = interpreter.execute(line, &program);
+
+            // RETURN must not go back to the line which typed GOSUB
+            let line = ascii::AsciiStr::from_ascii("GOTO 100 : PRINT 999").unwrap();
+            let error = interpreter.execute(line, &program).err().unwrap();
+            assert!(matches!(error.get_kind(), ErrorKind::ReturnOnEmptyStack), "{}", subroutine);
+            assert_eq!(interpreter.console().output(), "", "{}", subroutine);
+        }
+    }
+
+    #[test]
+    fn test_subtraction_without_spaces() {
+        assert_eq!(run_program("10 LET A = 5 : LET B = 2 : LET A_1 = 1\n20 PRINT A-B, A_1-B"), "3 -1 \n");
+    }
+
+    #[test]
+    fn test_for_next() {
+        assert_eq!(run_program("10 FOR I = 1 TO 3\n20 PRINT I\n30 NEXT I\n40 PRINT I"), "1 \n2 \n3 \n4 \n");
+        assert_eq!(run_program("10 FOR I = 10 TO 1 STEP -4 : PRINT I : NEXT"), "10 \n6 \n2 \n");
+        assert_eq!(run_program("10 FOR I = 5 TO 1\n20 PRINT I\n30 NEXT I"), "5 \n");
+    }
+
+    #[test]
+    fn test_nested_for_next() {
+        let source = "10 FOR I = 1 TO 2\n20 FOR J = 1 TO 2 : PRINT I * 10 + J : NEXT J\n30 NEXT I";
+        assert_eq!(run_program(source), "11 \n12 \n21 \n22 \n");
+        // NEXT with the outer variable closes the inner loop as well
+        let source = "10 FOR I = 1 TO 2\n20 FOR J = 1 TO 5\n30 PRINT I\n40 NEXT I";
+        assert_eq!(run_program(source), "1 \n2 \n");
+    }
+
+    #[test]
+    fn test_next_without_for() {
+        for source in ["10 NEXT", "10 FOR I = 1 TO 2\n20 NEXT J"] {
+            let program = ProgramStorage::load(source).unwrap();
+            let error = Interpreter::with_console(BufferConsole::new()).run(&program).err().unwrap();
+            assert!(matches!(error.get_kind(), ErrorKind::NextWithoutFor));
+        }
+    }
+
+    #[test]
+    fn test_variables_starting_with_rem() {
+        let source = "10 LET REMAINDER = 7 : LET REMARK = 2\n20 PRINT REMAINDER - REMARK";
+        assert_eq!(run_program(source), "5 \n");
+
+        let mut interpreter = Interpreter::with_console(BufferConsole::new());
+        let program = ProgramStorage::new();
+        let line = ascii::AsciiStr::from_ascii("LET REMAINDER = 7 : PRINT REMAINDER").unwrap();
+        interpreter.execute(line, &program).unwrap();
+        assert_eq!(interpreter.console().output(), "7 \n");
+    }
+
+    fn run_error(source: &str) -> ErrorKind {
+        let program = ProgramStorage::load(source).unwrap();
+        assert_engines_agree(&program);
+        Interpreter::with_console(BufferConsole::new()).run(&program).err().unwrap().get_kind()
+    }
+
+    #[test]
+    fn test_arrays() {
+        let source = "10 DIM A(3), M(2, 2)\n20 FOR I = 0 TO 3 : LET A(I) = I * I : NEXT\n30 LET M(1, 2) = A(3) + 1\n40 PRINT A(2), M(1, 2), M(2, 1)";
+        assert_eq!(run_program(source), "4 10 0 \n");
+
+        let program = ProgramStorage::load("10 DIM A(1)\n20 INPUT A(1)\n30 PRINT A(1) * 2").unwrap();
+        let mut interpreter = Interpreter::with_console(BufferConsole::with_input("21"));
+        interpreter.run(&program).unwrap();
+        assert_eq!(interpreter.console().output(), "A(1)? 42 \n");
+    }
+
+    #[test]
+    fn test_array_errors() {
+        assert!(matches!(run_error("10 DIM A(3)\n20 PRINT A(4)"), ErrorKind::SubscriptOutOfRange));
+        assert!(matches!(run_error("10 DIM A(3)\n20 LET A(0 - 1) = 1"), ErrorKind::SubscriptOutOfRange));
+        assert!(matches!(run_error("10 DIM A(3)\n20 PRINT A(1, 1)"), ErrorKind::WrongNumberOfSubscripts));
+        assert!(matches!(run_error("10 LET A(1) = 1"), ErrorKind::UndefinedArray));
+        assert!(matches!(run_error("10 DIM A(3), A(4)"), ErrorKind::ArrayAlreadyDimensioned));
+    }
+
+    #[test]
+    fn test_subscript_error_points_at_subscript() {
+        let program = ProgramStorage::load("10 DIM M(2, 2)\n20 PRINT M(1, 5)").unwrap();
+        let error = Interpreter::with_console(BufferConsole::new()).run(&program).err().unwrap();
+        let message = error.to_string();
+        let lines: Vec<_> = message.lines().collect();
+        assert_eq!(lines[2], "20 PRINT M(1, 5)");
+        assert_eq!(lines[3].find('^'), lines[2].find('5'));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_strings() {
+        let source = "10 LET A$ = \"TINY\"\n20 LET B$ = A$ + \" \" + \"BASIC\"\n30 PRINT B$, C$ + \"!\"\n40 IF A$ < \"TINZ\" THEN PRINT \"LESS\"\n50 IF A$ = \"TINY\" THEN PRINT \"EQUAL\"";
+        assert_eq!(run_program(source), "TINY BASIC ! \nLESS \nEQUAL \n");
+        assert_eq!(run_program("10 DIM N$(2)\n20 LET N$(1) = \"X\"\n30 PRINT N$(0) + N$(1) + \"Y\""), "XY \n");
+    }
+
+    #[test]
+    fn test_input_string() {
+        let program = ProgramStorage::load("10 INPUT N$, A\n20 PRINT \"HELLO, \" + N$, A").unwrap();
+        let mut interpreter = Interpreter::with_console(BufferConsole::with_input("WORLD 42\n7"));
+        interpreter.run(&program).unwrap();
+        assert_eq!(interpreter.console().output(), "N$? A? HELLO, WORLD 42 7 \n");
+        assert_eq!(interpreter.get_variable("N$"), Some(&Value::String(ascii::AsciiString::from_ascii("WORLD 42").unwrap())));
+    }
+
+    #[test]
+    fn test_type_mismatch() {
+        for source in [
+            "10 LET A = \"X\"",
+            "10 LET A$ = 1",
+            "10 PRINT \"X\" + 1",
+            "10 PRINT \"X\" - \"Y\"",
+            "10 PRINT \"X\" * 2",
+            "10 PRINT -A$",
+            "10 IF A$ = 0 THEN PRINT 1",
+            "10 GOTO \"X\"",
+            "10 FOR A$ = 1 TO 2"
+        ] {
+            assert!(matches!(run_error(source), ErrorKind::TypeMismatch), "{}", source);
+        }
+    }
+
+    #[test]
+    fn test_functions() {
+        assert_eq!(run_program("10 PRINT MAX(4, 2 * 5, ABS(0 - 8))"), "10 \n");
+        // An array hides the function of the same name
+        assert_eq!(run_program("10 DIM ABS(1)\n20 PRINT ABS(1)"), "0 \n");
+        assert!(matches!(run_error("10 PRINT SQR(4)"), ErrorKind::UnknownFunction));
+        assert!(matches!(run_error("10 PRINT ABS(1, 2)"), ErrorKind::WrongNumberOfArguments));
+        assert!(matches!(run_error("10 PRINT MAX(1)"), ErrorKind::WrongNumberOfArguments));
+        assert!(matches!(run_error("10 PRINT RND(0)"), ErrorKind::InvalidArgument));
+        assert!(matches!(run_error("10 PRINT ABS(\"X\")"), ErrorKind::TypeMismatch));
+    }
+
+    #[test]
+    fn test_randomize() {
+        let source = "10 RANDOMIZE 42\n20 FOR I = 1 TO 20 : PRINT RND(6) : NEXT";
+        let first = run_program(source);
+        assert_eq!(first, run_program(source));
+        assert_ne!(first, run_program("10 RANDOMIZE 43\n20 FOR I = 1 TO 20 : PRINT RND(6) : NEXT"));
+    }
+
+    #[test]
+    #[cfg(not(feature = "number-f64"))]
+    fn test_operator_chains() {
+        assert_eq!(run_program("10 LET A = 7 : LET B = 2 : LET C = 3\n20 PRINT A/B*C, A - B - C, A*(B+C)"), "9 2 35 \n");
+    }
+
+    /// Evaluator of the expressions defined by the DDJ Tiny BASIC grammar,
+    /// written as close to the grammar as possible:
+    ///
+    /// expression ::= (+|-|ε) term ((+|-) term)*
+    /// term ::= factor ((*|/) factor)*
+    /// factor ::= number | (expression)
+    ///
+    /// `None` is returned if the expression cannot be evaluated with 16-bit numbers.
+    #[cfg(not(feature = "number-f64"))]
+    struct ReferenceEvaluator<'a> {
+        text: &'a [u8],
+        cur: usize
+    }
+
+    #[cfg(not(feature = "number-f64"))]
+    impl ReferenceEvaluator<'_> {
+        fn evaluate(text: &str) -> Option<i16> {
+            let mut evaluator = ReferenceEvaluator { text: text.as_bytes(), cur: 0 };
+            let value = evaluator.expression()?;
+            assert_eq!(evaluator.cur, text.len(), "Generated expression should be consumed fully");
+            Some(value)
+        }
+
+        fn peek(&self) -> Option<u8> {
+            self.text.get(self.cur).copied()
+        }
+
+        fn expression(&mut self) -> Option<i16> {
+            let mut value = match self.peek() {
+                Some(b'+') => { self.cur += 1; self.term()? },
+                Some(b'-') => { self.cur += 1; self.term()?.checked_neg()? },
+                _ => self.term()?,
+            };
+            while let Some(op @ (b'+' | b'-')) = self.peek() {
+                self.cur += 1;
+                let other = self.term()?;
+                value = if op == b'+' { value.checked_add(other)? } else { value.checked_sub(other)? };
+            }
+            Some(value)
+        }
+
+        fn term(&mut self) -> Option<i16> {
+            let mut value = self.factor()?;
+            while let Some(op @ (b'*' | b'/')) = self.peek() {
+                self.cur += 1;
+                let other = self.factor()?;
+                value = if op == b'*' { value.checked_mul(other)? } else { value.checked_div(other)? };
+            }
+            Some(value)
+        }
+
+        fn factor(&mut self) -> Option<i16> {
+            if self.peek() == Some(b'(') {
+                self.cur += 1;
+                let value = self.expression()?;
+                assert_eq!(self.peek(), Some(b')'));
+                self.cur += 1;
+                return Some(value);
+            }
+            let start = self.cur;
+            while self.peek().is_some_and(|ch| ch.is_ascii_digit()) {
+                self.cur += 1;
+            }
+            std::str::from_utf8(&self.text[start..self.cur]).unwrap().parse().ok()
+        }
+    }
+
+    #[cfg(not(feature = "number-f64"))]
+    fn generate_expression(prng: &crate::tiny_basic::random::Prng, depth: u32) -> String {
+        let mut expression = String::new();
+        match prng.below(3) {
+            0 => expression.push('-'),
+            1 => expression.push('+'),
+            _ => (),
+        }
+        for i in 0..=prng.below(3) {
+            if i > 0 {
+                expression.push(if prng.below(2) == 0 { '+' } else { '-' });
+            }
+            for j in 0..=prng.below(3) {
+                if j > 0 {
+                    expression.push(if prng.below(2) == 0 { '*' } else { '/' });
+                }
+                if depth > 0 && prng.below(4) == 0 {
+                    expression += &format!("({})", generate_expression(prng, depth - 1));
+                } else {
+                    expression += &(prng.below(20) + 1).to_string();
+                }
+            }
+        }
+        expression
+    }
+
+    /// Compares the interpreter with the reference. With 16-bit numbers, the expressions
+    /// the reference cannot evaluate must fail in the interpreter as well.
+    #[cfg(not(feature = "number-f64"))]
+    fn conforms_to_ddj_grammar(expression: &str) -> bool {
+        let source = format!("10 PRINT {}", expression);
+        match ReferenceEvaluator::evaluate(expression) {
+            Some(expected) => {
+                assert_eq!(run_program(&source), format!("{} \n", expected), "{}", expression);
+                true
+            },
+            #[cfg(not(any(feature = "number-i32", feature = "number-i64", feature = "number-big")))]
+            None => {
+                assert!(matches!(run_error(&source), ErrorKind::Overflow | ErrorKind::DivisionByZero), "{}", expression);
+                false
+            },
+            #[cfg(any(feature = "number-i32", feature = "number-i64", feature = "number-big"))]
+            None => false,
+        }
+    }
+
+    #[test]
+    #[cfg(not(feature = "number-f64"))]
+    fn test_expressions_conform_to_ddj_grammar() {
+        // The sign applies to the whole first term, so -(2*16384) overflows at the edge of 16 bits
+        assert!(!conforms_to_ddj_grammar("-2*16384"));
+        assert!(conforms_to_ddj_grammar("-2*16383"));
+        assert!(conforms_to_ddj_grammar("-16384-16384"));
+
+        let prng = crate::tiny_basic::random::Prng::new(2025);
+        let mut compared = 0;
+        for _ in 0..2000 {
+            let expression = generate_expression(&prng, 3);
+            if conforms_to_ddj_grammar(&expression) {
+                compared += 1;
+            }
+        }
+        assert!(compared > 1000);
+    }
+
+    #[cfg(not(any(feature = "number-i32", feature = "number-i64", feature = "number-f64", feature = "number-big")))]
+    fn run_error_with_location(source: &str) -> (ErrorKind, Option<i16>, Option<usize>) {
+        let program = ProgramStorage::load(source).unwrap();
+        assert_engines_agree(&program);
+        let error = Interpreter::with_console(BufferConsole::new()).run(&program).err().unwrap();
+        (error.get_kind(), error.get_line_number().map(Into::into), error.get_location())
+    }
+
+    #[test]
+    #[cfg(not(any(feature = "number-i32", feature = "number-i64", feature = "number-f64", feature = "number-big")))]
+    fn test_checked_arithmetic() {
+        let (kind, line, column) = run_error_with_location("10 PRINT 1\n20 PRINT 2 + 32767");
+        assert!(matches!(kind, ErrorKind::Overflow));
+        assert_eq!((line, column), (Some(20), Some(8)));
+
+        let (kind, line, column) = run_error_with_location("10 LET A = 0\n20 PRINT 10 / A");
+        assert!(matches!(kind, ErrorKind::DivisionByZero));
+        assert_eq!((line, column), (Some(20), Some(11)));
+
+        // The unary minus overflows where it is written
+        let (kind, _, column) = run_error_with_location("10 LET A = -32767 - 1 : PRINT -A");
+        assert!(matches!(kind, ErrorKind::Overflow));
+        assert_eq!(column, Some(27));
+    }
+
+    #[test]
+    #[cfg(not(any(feature = "number-i32", feature = "number-i64", feature = "number-f64", feature = "number-big")))]
+    fn test_arithmetic_modes() {
+        let program = ProgramStorage::load("10 PRINT 32767 + 1, 200 * 200, -32767 - 2").unwrap();
+        let outcome = |mode| run_differential(&program, "", mode).unwrap().output;
+        assert_eq!(outcome(ArithmeticMode::Wrapping), "-32768 -25536 32767 \n");
+        assert_eq!(outcome(ArithmeticMode::Saturating), "32767 32767 -32768 \n");
+    }
+
+    #[test]
+    #[cfg(not(any(feature = "number-f64", feature = "number-big")))]
+    fn test_for_to_max_in_every_mode() {
+        use crate::tiny_basic::types::{ArithmeticMode, Number};
+
+        let source = format!(
+            "10 FOR I = {} TO {}\n20 PRINT I\n30 LET N = N + 1 : IF N = 4 THEN END\n40 NEXT I",
+            Number::MAX - 1,
+            Number::MAX
+        );
+        let program = ProgramStorage::load(&source).unwrap();
+        let outcome = |mode| run_differential(&program, "", mode).unwrap();
+
+        let checked = outcome(ArithmeticMode::Checked);
+        assert_eq!(checked.output, format!("{} \n{} \n", Number::MAX - 1, Number::MAX));
+        assert!(checked.error.unwrap().contains("overflow"));
+
+        let wrapping = outcome(ArithmeticMode::Wrapping);
+        assert_eq!(wrapping.output, format!("{} \n{} \n{} \n{} \n", Number::MAX - 1, Number::MAX, Number::MIN, Number::MIN + 1));
+        assert_eq!(wrapping.error, None);
+
+        let saturating = outcome(ArithmeticMode::Saturating);
+        assert_eq!(saturating.output, format!("{} \n{} \n{} \n{} \n", Number::MAX - 1, Number::MAX, Number::MAX, Number::MAX));
+        assert_eq!(saturating.error, None);
+    }
+
+    #[test]
+    #[cfg(not(any(feature = "number-f64", feature = "number-big")))]
+    fn test_abs_in_every_mode() {
+        use crate::tiny_basic::types::{ArithmeticMode, Number};
+
+        let program = ProgramStorage::load(&format!("10 LET A = {} - 1\n20 PRINT ABS(A), ABS(-5)", Number::MIN + 1)).unwrap();
+        let outcome = |mode| run_differential(&program, "", mode).unwrap();
+        assert!(outcome(ArithmeticMode::Checked).error.unwrap().contains("overflow"));
+        assert_eq!(outcome(ArithmeticMode::Wrapping).output, format!("{} 5 \n", Number::MIN));
+        assert_eq!(outcome(ArithmeticMode::Saturating).output, format!("{} 5 \n", Number::MAX));
+    }
+
+    #[test]
+    #[cfg(all(any(feature = "number-i32", feature = "number-i64"), not(any(feature = "number-f64", feature = "number-big"))))]
+    fn test_wide_integers() {
+        assert_eq!(run_program("10 PRINT 32767 + 1, 200 * 200, -32767 - 2"), "32768 40000 -32769 \n");
+        assert!(matches!(run_error("10 GOTO 40000"), ErrorKind::InvalidLineIndex));
+    }
+
+    #[test]
+    #[cfg(all(feature = "number-f64", not(feature = "number-big")))]
+    fn test_floating_point() {
+        assert_eq!(run_program("10 PRINT 3.14 * 2, 7 / 2, -1.5, 32767 + 1"), "6.28 3.5 -1.5 32768 \n");
+        assert_eq!(run_program("10 FOR I = 0 TO 1 STEP 0.5 : PRINT I : NEXT"), "0 \n0.5 \n1 \n");
+        assert_eq!(run_program("10 GOTO 20.9\n20 PRINT 20"), "20 \n");
+        assert_eq!(run_program("10 DIM A(2.5)\n20 LET A(2) = 0.25\n30 PRINT A(2.9)"), "0.25 \n");
+        assert!(matches!(run_error("10 LET A = 10\n20 LET A = A * A : GOTO 20"), ErrorKind::Overflow));
+    }
+
+    #[test]
+    #[cfg(feature = "number-big")]
+    fn test_big_integers() {
+        let source = "10 LET F = 1\n20 FOR I = 1 TO 30 : LET F = F * I : NEXT\n30 PRINT F, F / 1000000000000000000000, -F";
+        assert_eq!(run_program(source), "265252859812191058636308480000000 265252859812 -265252859812191058636308480000000 \n");
+        assert!(matches!(run_error("10 GOTO 100000000000000000000"), ErrorKind::InvalidLineIndex));
+
+        let program = ProgramStorage::load("10 INPUT A\n20 PRINT A * A").unwrap();
+        let mut interpreter = Interpreter::with_console(BufferConsole::with_input("-12345678901234567890\n"));
+        interpreter.run(&program).unwrap();
+        assert_eq!(interpreter.console().output(), "A? 152415787532388367501905199875019052100 \n");
+    }
+}
@@ -0,0 +1,144 @@
+/*
+    Tiny BASIC interpreter written in Rust
+    Copyright (C) 2025 Artyom Makarov
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+//! Interruption of a running program with Ctrl-C.
+//!
+//! The handler of SIGINT (of Ctrl-C on Windows) only raises a flag, which the engines
+//! check between the lines of a program. An interrupted program stops with a
+//! [`ErrorKind::Break`](crate::tiny_basic::error::ErrorKind::Break) error.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// The flag raised by the handler installed with [`install_handler`]
+pub static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// Makes Ctrl-C interrupt the running program instead of terminating the process
+pub fn install_handler() {
+    platform::install_handler();
+}
+
+/// Lowers the flag, returning whether it was raised
+pub(crate) fn take(flag: &AtomicBool) -> bool {
+    flag.swap(false, Ordering::Relaxed)
+}
+
+#[cfg(unix)]
+mod platform {
+    use std::ffi::c_int;
+    use std::sync::atomic::Ordering;
+
+    const SIGINT: c_int = 2;
+
+    extern "C" {
+        fn signal(signum: c_int, handler: extern "C" fn(c_int)) -> usize;
+    }
+
+    extern "C" fn on_interrupt(_signum: c_int) {
+        super::INTERRUPTED.store(true, Ordering::Relaxed);
+    }
+
+    pub fn install_handler() {
+        // SAFETY: the handler only stores to an atomic, which is async-signal-safe
+        unsafe {
+            signal(SIGINT, on_interrupt);
+        }
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    use std::sync::atomic::Ordering;
+
+    const CTRL_C_EVENT: u32 = 0;
+
+    extern "system" {
+        fn SetConsoleCtrlHandler(handler: extern "system" fn(u32) -> i32, add: i32) -> i32;
+    }
+
+    extern "system" fn on_interrupt(event: u32) -> i32 {
+        if event == CTRL_C_EVENT {
+            super::INTERRUPTED.store(true, Ordering::Relaxed);
+            1
+        } else {
+            0
+        }
+    }
+
+    pub fn install_handler() {
+        // SAFETY: the handler only stores to an atomic
+        unsafe {
+            SetConsoleCtrlHandler(on_interrupt, 1);
+        }
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+mod platform {
+    pub fn install_handler() {}
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    use crate::tiny_basic::console::{BufferConsole, Console};
+    use crate::tiny_basic::engine::Engine;
+    use crate::tiny_basic::error::ErrorKind;
+    use crate::tiny_basic::interpreter::Interpreter;
+    use crate::tiny_basic::program_storage::ProgramStorage;
+
+    /// Raises the flag whenever something is printed, as if Ctrl-C was pressed right after
+    struct InterruptingConsole {
+        output: BufferConsole,
+        flag: &'static AtomicBool
+    }
+
+    impl Console for InterruptingConsole {
+        fn write(&mut self, text: &str) -> std::io::Result<()> {
+            self.flag.store(true, Ordering::Relaxed);
+            self.output.write(text)
+        }
+
+        fn read_line(&mut self) -> std::io::Result<Option<String>> {
+            self.output.read_line()
+        }
+    }
+
+    #[test]
+    fn test_break() {
+        for (engine, source, line) in [
+            (Engine::Tree, "10 PRINT 1 : GOTO 10", 10),
+            (Engine::Tree, "10 PRINT 1\n20 GOTO 10", 20),
+            (Engine::Bytecode, "10 PRINT 1 : GOTO 10", 10),
+            (Engine::Bytecode, "10 FOR I = 1 TO 2 : LET I = 1 : PRINT I : NEXT I", 10),
+            (Engine::Il, "10 PRINT 1\n20 GOTO 10", 20),
+        ] {
+            let flag = Box::leak(Box::new(AtomicBool::new(true)));
+            let program = ProgramStorage::load(source).unwrap();
+            let mut interpreter = Interpreter::with_console(InterruptingConsole { output: BufferConsole::new(), flag });
+            interpreter.set_interrupt_flag(flag);
+
+            // The flag raised before the run does not stop it
+            let error = engine.run(&mut interpreter, &program).err().unwrap();
+            assert!(matches!(error.get_kind(), ErrorKind::Break), "{} {}", engine, source);
+            assert_eq!(error.get_line_number().map(Into::into), Some(line), "{} {}", engine, source);
+            assert_eq!(error.to_string(), format!("Error: Break in line {}", line));
+            assert_eq!(interpreter.console().output.output().lines().count(), 1, "{} {}", engine, source);
+        }
+    }
+}
@@ -0,0 +1,338 @@
+/*
+    Tiny BASIC interpreter written in Rust
+    Copyright (C) 2025 Artyom Makarov
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+//! Splits a line into tokens.
+//!
+//! [`tokenize`] turns the text of a line into a list of [`SpannedToken`]s, which the
+//! interpreter and the syntax check walk with a [`TokenStream`]. The characters are
+//! scanned by [`AsciiCharStream`], so the rules for identifiers, numbers and strings
+//! are defined there and nowhere else.
+
+use ascii::{AsciiChar, AsciiStr};
+
+use crate::tiny_basic;
+use crate::tiny_basic::char_stream::{ArithmeticOperator, AsciiCharStream, Command, Keyword, RelationalOperator, Statement};
+use crate::tiny_basic::error::{Error, ErrorContext, ErrorKind};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Token<'a> {
+    Keyword(Keyword),
+    Identifier(&'a AsciiStr),
+    /// Text of a number literal, which is parsed by the numeric back-end
+    Number(&'a AsciiStr),
+    /// Contents of a string literal without the quotes
+    String(&'a AsciiStr),
+    Operator(ArithmeticOperator),
+    RelationalOperator(RelationalOperator),
+    /// One of `(`, `)`, `,` and `:`
+    Punctuation(AsciiChar),
+    /// Text of an apostrophe comment or of a remark after REM
+    Comment(&'a AsciiStr)
+}
+
+/// Range of bytes of the line occupied by a token
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpannedToken<'a> {
+    pub token: Token<'a>,
+    pub span: Span
+}
+
+pub fn tokenize(line: &AsciiStr) -> tiny_basic::Result<'_, Vec<SpannedToken<'_>>> {
+    let mut stream = AsciiCharStream::from_ascii_str(line);
+    stream.trim_start();
+    let mut tokens = Vec::new();
+    while !stream.is_empty() {
+        let start = stream.get_location();
+        let token = next_token(&mut stream)?;
+        // Spans do not include the whitespace after the token
+        let end = start + line[start..stream.get_location()].trim_end().len();
+        tokens.push(SpannedToken { token, span: Span { start, end } });
+
+        if token == Token::Keyword(Keyword::Rem) {
+            let start = stream.get_location();
+            let remark = stream.flush();
+            tokens.push(SpannedToken { token: Token::Comment(remark), span: Span { start, end: line.len() } });
+        }
+    }
+    Ok(tokens)
+}
+
+fn next_token<'a>(stream: &mut AsciiCharStream<'a>) -> tiny_basic::Result<'a, Token<'a>> {
+    // A word is a keyword if it is one as a whole, e.g. TOP is an identifier, not TO followed by P
+    let mut lookahead = *stream;
+    if let Some(keyword) = lookahead.consume_keyword() {
+        *stream = lookahead;
+        return Ok(Token::Keyword(keyword));
+    }
+
+    if let Some(comment) = stream.consume_comment() {
+        Ok(Token::Comment(comment))
+    } else if let Some(name) = stream.consume_var() {
+        Ok(Token::Identifier(name))
+    } else if let Some(number) = stream.consume_number() {
+        Ok(Token::Number(number))
+    } else if let Some(string) = stream.consume_string()? {
+        Ok(Token::String(string))
+    } else if let Some(operator) = stream.consume_arithmetic_operator() {
+        Ok(Token::Operator(operator))
+    } else if let Some(operator) = stream.consume_relop() {
+        Ok(Token::RelationalOperator(operator))
+    } else if let Some(ch) = stream.consume_char_if(|ch| matches!(ch, AsciiChar::ParenOpen | AsciiChar::ParenClose | AsciiChar::Comma | AsciiChar::Colon)) {
+        Ok(Token::Punctuation(ch))
+    } else {
+        Err(Error::from_context(stream, ErrorKind::UnexpectedCharacter, None))
+    }
+}
+
+/// Cursor over the tokens of a line. Copying it saves the position, as with [`AsciiCharStream`].
+#[derive(Clone, Copy)]
+pub struct TokenStream<'t, 'a> {
+    line: &'a AsciiStr,
+    tokens: &'t [SpannedToken<'a>],
+    cur: usize
+}
+
+impl<'t, 'a> TokenStream<'t, 'a> {
+    pub fn new(line: &'a AsciiStr, tokens: &'t [SpannedToken<'a>]) -> Self {
+        Self { line, tokens, cur: 0 }
+    }
+
+    pub fn peek(&self) -> Option<Token<'a>> {
+        self.tokens.get(self.cur).map(|token| token.token)
+    }
+
+    fn consume<T>(&mut self, extract: impl Fn(Token<'a>) -> Option<T>) -> Option<T> {
+        let value = extract(self.peek()?)?;
+        self.cur += 1;
+        Some(value)
+    }
+
+    pub fn consume_keyword(&mut self) -> Option<Keyword> {
+        self.consume(|token| match token {
+            Token::Keyword(keyword) => Some(keyword),
+            _ => None,
+        })
+    }
+
+    pub fn consume_statement(&mut self) -> Option<Statement> {
+        self.consume(|token| match token {
+            Token::Keyword(keyword) => keyword.statement(),
+            _ => None,
+        })
+    }
+
+    pub fn consume_command(&mut self) -> Option<Command> {
+        self.consume(|token| match token {
+            Token::Keyword(keyword) => keyword.command(),
+            _ => None,
+        })
+    }
+
+    pub fn consume_var(&mut self) -> Option<&'a AsciiStr> {
+        self.consume(|token| match token {
+            Token::Identifier(name) => Some(name),
+            _ => None,
+        })
+    }
+
+    pub fn consume_number(&mut self) -> Option<&'a AsciiStr> {
+        self.consume(|token| match token {
+            Token::Number(number) => Some(number),
+            _ => None,
+        })
+    }
+
+    pub fn consume_string(&mut self) -> Option<&'a AsciiStr> {
+        self.consume(|token| match token {
+            Token::String(string) => Some(string),
+            _ => None,
+        })
+    }
+
+    pub fn consume_comment(&mut self) -> Option<&'a AsciiStr> {
+        self.consume(|token| match token {
+            Token::Comment(comment) => Some(comment),
+            _ => None,
+        })
+    }
+
+    pub fn consume_arithmetic_operator(&mut self) -> Option<ArithmeticOperator> {
+        self.consume(|token| match token {
+            Token::Operator(operator) => Some(operator),
+            _ => None,
+        })
+    }
+
+    pub fn consume_relop(&mut self) -> Option<RelationalOperator> {
+        self.consume(|token| match token {
+            Token::RelationalOperator(operator) => Some(operator),
+            _ => None,
+        })
+    }
+
+    /// Consumes a punctuation mark or a single character operator, e.g. `=` of LET
+    pub fn consume_char_if<F>(&mut self, predicate: F) -> Option<AsciiChar>
+    where F: Fn(&AsciiChar) -> bool {
+        let line = self.line;
+        let span = self.tokens.get(self.cur)?.span;
+        self.consume(|token| match token {
+            Token::Punctuation(_) | Token::Operator(_) | Token::RelationalOperator(_) if span.end - span.start == 1 => {
+                Some(line[span.start]).filter(&predicate)
+            },
+            _ => None,
+        })
+    }
+
+    pub fn consume_char(&mut self, ch: AsciiChar) -> Option<()> {
+        self.consume_char_if(|tested_ch| *tested_ch == ch).and(Some(()))
+    }
+
+    /// Skips the rest of the line, returning its text
+    pub fn flush(&mut self) -> &'a AsciiStr {
+        let remaining = &self.line[self.get_location()..];
+        self.cur = self.tokens.len();
+        remaining
+    }
+
+    /// Checks if the statement is over: the line ends, or the next statement or a comment starts
+    pub fn is_end_of_statement(&self) -> bool {
+        matches!(self.peek(), None | Some(Token::Punctuation(AsciiChar::Colon)) | Some(Token::Comment(_)))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cur >= self.tokens.len()
+    }
+}
+
+impl<'a> ErrorContext<'a> for TokenStream<'_, 'a> {
+    fn get_stream(&self) -> &'a AsciiStr {
+        self.line
+    }
+
+    /// Start of the next token, or the end of the line
+    fn get_location(&self) -> usize {
+        self.tokens
+            .get(self.cur)
+            .map_or(self.line.len(), |token| token.span.start)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ascii::{AsciiChar, AsciiStr};
+
+    use crate::tiny_basic::char_stream::{ArithmeticOperator, Keyword, RelationalOperator};
+    use crate::tiny_basic::error::{ErrorContext, ErrorKind};
+
+    use super::{tokenize, Span, Token, TokenStream};
+
+    fn tokens(line: &str) -> Vec<Token<'_>> {
+        tokenize(AsciiStr::from_ascii(line).unwrap())
+            .unwrap()
+            .into_iter()
+            .map(|token| token.token)
+            .collect()
+    }
+
+    fn ascii(text: &str) -> &AsciiStr {
+        AsciiStr::from_ascii(text).unwrap()
+    }
+
+    #[test]
+    fn test_tokens() {
+        assert_eq!(tokens("IF A-B <= 10 THEN PRINT \"X\", B$(1)"), [
+            Token::Keyword(Keyword::If),
+            Token::Identifier(ascii("A")),
+            Token::Operator(ArithmeticOperator::Subtract),
+            Token::Identifier(ascii("B")),
+            Token::RelationalOperator(RelationalOperator::LessEqual),
+            Token::Number(ascii("10")),
+            Token::Keyword(Keyword::Then),
+            Token::Keyword(Keyword::Print),
+            Token::String(ascii("X")),
+            Token::Punctuation(AsciiChar::Comma),
+            Token::Identifier(ascii("B$")),
+            Token::Punctuation(AsciiChar::ParenOpen),
+            Token::Number(ascii("1")),
+            Token::Punctuation(AsciiChar::ParenClose),
+        ]);
+        assert_eq!(tokens("LET TOP = STEPS"), [
+            Token::Keyword(Keyword::Let),
+            Token::Identifier(ascii("TOP")),
+            Token::RelationalOperator(RelationalOperator::Equal),
+            Token::Identifier(ascii("STEPS")),
+        ]);
+    }
+
+    #[test]
+    fn test_comments() {
+        assert_eq!(tokens("REM ARK: PRINT 1"), [Token::Keyword(Keyword::Rem), Token::Comment(ascii("ARK: PRINT 1"))]);
+        assert_eq!(tokens("REM:"), [Token::Keyword(Keyword::Rem), Token::Comment(ascii(":"))]);
+        assert_eq!(tokens("LET REMAINDER = 7"), [
+            Token::Keyword(Keyword::Let),
+            Token::Identifier(ascii("REMAINDER")),
+            Token::RelationalOperator(RelationalOperator::Equal),
+            Token::Number(ascii("7")),
+        ]);
+        assert_eq!(tokens("REM"), [Token::Keyword(Keyword::Rem), Token::Comment(ascii(""))]);
+        assert_eq!(tokens("END ' the end"), [Token::Keyword(Keyword::End), Token::Comment(ascii(" the end"))]);
+    }
+
+    #[test]
+    fn test_spans() {
+        let spans: Vec<_> = tokenize(ascii("  LET AB = 12 "))
+            .unwrap()
+            .into_iter()
+            .map(|token| token.span)
+            .collect();
+        assert_eq!(spans, [Span { start: 2, end: 5 }, Span { start: 6, end: 8 }, Span { start: 9, end: 10 }, Span { start: 11, end: 13 }]);
+    }
+
+    #[test]
+    fn test_errors() {
+        let error = tokenize(ascii("PRINT 1; 2")).err().unwrap();
+        assert!(matches!(error.get_kind(), ErrorKind::UnexpectedCharacter));
+        assert_eq!(error.get_location(), Some(7));
+        assert!(matches!(tokenize(ascii("PRINT \"A")).err().unwrap().get_kind(), ErrorKind::Expected('"')));
+    }
+
+    #[test]
+    fn test_token_stream() {
+        let line = ascii("LET A = 1 : PRINT A");
+        let tokens = tokenize(line).unwrap();
+        let mut stream = TokenStream::new(line, &tokens);
+        assert!(stream.consume_command().is_none());
+        assert!(stream.consume_statement().is_some());
+        assert_eq!(stream.consume_var(), Some(ascii("A")));
+        assert!(stream.consume_char(AsciiChar::Equal).is_some());
+        assert_eq!(stream.get_location(), 8);
+        assert_eq!(stream.consume_number(), Some(ascii("1")));
+        assert!(stream.is_end_of_statement());
+        let resumed = stream;
+        assert_eq!(stream.flush().as_str(), ": PRINT A");
+        assert!(stream.is_empty());
+        stream = resumed;
+        assert!(stream.consume_char(AsciiChar::Colon).is_some());
+    }
+}
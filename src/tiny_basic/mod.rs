@@ -16,12 +16,28 @@
     along with this program.  If not, see <https://www.gnu.org/licenses/>.
 */
 
-pub mod code_line;
-pub mod error;
-pub mod types;
-pub mod char_stream;
-pub mod program_storage;
-pub mod interpreter;
-pub mod repl;
+pub(crate) mod array;
+#[cfg(feature = "number-big")]
+pub(crate) mod big_int;
+pub(crate) mod code_line;
+pub(crate) mod error;
+pub(crate) mod functions;
+pub(crate) mod number;
+pub(crate) mod types;
+pub(crate) mod char_stream;
+pub(crate) mod console;
+pub(crate) mod program_storage;
+pub(crate) mod random;
+pub(crate) mod interrupt;
+pub(crate) mod lexer;
+pub(crate) mod ast;
+pub(crate) mod parser;
+pub(crate) mod interpreter;
+pub(crate) mod bytecode;
+pub(crate) mod vm;
+pub(crate) mod il;
+pub(crate) mod engine;
+pub(crate) mod repl;
+pub(crate) mod syntax_check;
 
 pub type Result<'ctx, T> = std::result::Result<T, error::Error<'ctx>>;
\ No newline at end of file
@@ -0,0 +1,277 @@
+/*
+    Tiny BASIC interpreter written in Rust
+    Copyright (C) 2025 Artyom Makarov
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+//! Numeric back-ends of the interpreter.
+//!
+//! The type of [`Number`](crate::tiny_basic::types::Number) is selected at compile time by
+//! one of the `number-i32`, `number-i64`, `number-f64` or `number-big` features, `i16` being
+//! the default as in the original Tiny BASIC. Everything the interpreter needs from a number
+//! goes through the [`Numeric`] trait.
+
+use std::fmt;
+
+#[cfg(feature = "number-big")]
+use crate::tiny_basic::big_int::BigInt;
+use crate::tiny_basic::error::ErrorKind;
+
+/// Operations which the interpreter performs on numbers
+pub trait Numeric: Clone + PartialEq + PartialOrd + fmt::Debug + fmt::Display {
+    fn zero() -> Self;
+    fn one() -> Self;
+    /// The smallest and the largest numbers, if the type is bounded
+    fn limits() -> Option<(Self, Self)>;
+
+    /// Parses a literal returned by [`consume_number`](crate::tiny_basic::char_stream::AsciiCharStream::consume_number)
+    /// or typed in by the user
+    fn parse_literal(text: &str) -> Result<Self, ErrorKind>;
+
+    fn from_i64(value: i64) -> Option<Self>;
+    /// The integral part of the number, if it fits into `i64`
+    fn to_i64(&self) -> Option<i64>;
+
+    fn checked_add(self, rhs: Self) -> Option<Self>;
+    fn checked_sub(self, rhs: Self) -> Option<Self>;
+    fn checked_mul(self, rhs: Self) -> Option<Self>;
+    /// The divisor is never zero
+    fn checked_div(self, rhs: Self) -> Option<Self>;
+
+    fn wrapping_add(self, rhs: Self) -> Self;
+    fn wrapping_sub(self, rhs: Self) -> Self;
+    fn wrapping_mul(self, rhs: Self) -> Self;
+    fn wrapping_div(self, rhs: Self) -> Self;
+
+    fn saturating_add(self, rhs: Self) -> Self;
+    fn saturating_sub(self, rhs: Self) -> Self;
+    fn saturating_mul(self, rhs: Self) -> Self;
+    fn saturating_div(self, rhs: Self) -> Self;
+
+    /// -1, 0 or 1
+    fn sign(&self) -> Self;
+
+    /// Writes the number as PRINT shows it
+    fn format(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self)
+    }
+}
+
+macro_rules! impl_numeric_for_integer {
+    ($($t:ty),*) => {
+        $(
+            impl Numeric for $t {
+                fn zero() -> Self { 0 }
+                fn one() -> Self { 1 }
+                fn limits() -> Option<(Self, Self)> { Some((<$t>::MIN, <$t>::MAX)) }
+
+                fn parse_literal(text: &str) -> Result<Self, ErrorKind> {
+                    Ok(text.parse::<$t>()?)
+                }
+
+                fn from_i64(value: i64) -> Option<Self> {
+                    Self::try_from(value).ok()
+                }
+
+                fn to_i64(&self) -> Option<i64> {
+                    i64::try_from(*self).ok()
+                }
+
+                fn checked_add(self, rhs: Self) -> Option<Self> { <$t>::checked_add(self, rhs) }
+                fn checked_sub(self, rhs: Self) -> Option<Self> { <$t>::checked_sub(self, rhs) }
+                fn checked_mul(self, rhs: Self) -> Option<Self> { <$t>::checked_mul(self, rhs) }
+                fn checked_div(self, rhs: Self) -> Option<Self> { <$t>::checked_div(self, rhs) }
+
+                fn wrapping_add(self, rhs: Self) -> Self { <$t>::wrapping_add(self, rhs) }
+                fn wrapping_sub(self, rhs: Self) -> Self { <$t>::wrapping_sub(self, rhs) }
+                fn wrapping_mul(self, rhs: Self) -> Self { <$t>::wrapping_mul(self, rhs) }
+                fn wrapping_div(self, rhs: Self) -> Self { <$t>::wrapping_div(self, rhs) }
+
+                fn saturating_add(self, rhs: Self) -> Self { <$t>::saturating_add(self, rhs) }
+                fn saturating_sub(self, rhs: Self) -> Self { <$t>::saturating_sub(self, rhs) }
+                fn saturating_mul(self, rhs: Self) -> Self { <$t>::saturating_mul(self, rhs) }
+                fn saturating_div(self, rhs: Self) -> Self { <$t>::saturating_div(self, rhs) }
+
+                fn sign(&self) -> Self { <$t>::signum(*self) }
+            }
+        )*
+    };
+}
+
+impl_numeric_for_integer!(i16, i32, i64);
+
+/// Floating point numbers overflow to infinity, which is treated as an overflow.
+/// Wrapping makes no sense for them, so the wrapping operations saturate.
+impl Numeric for f64 {
+    fn zero() -> Self { 0.0 }
+    fn one() -> Self { 1.0 }
+    fn limits() -> Option<(Self, Self)> { Some((f64::MIN, f64::MAX)) }
+
+    fn parse_literal(text: &str) -> Result<Self, ErrorKind> {
+        text.parse::<f64>()
+            .ok()
+            .filter(|number| number.is_finite())
+            .ok_or(ErrorKind::NumberParseError(std::num::IntErrorKind::InvalidDigit))
+    }
+
+    fn from_i64(value: i64) -> Option<Self> {
+        Some(value as f64)
+    }
+
+    fn to_i64(&self) -> Option<i64> {
+        let integral = self.trunc();
+        // i64::MAX is not representable, the nearest f64 is 2^63
+        (integral >= i64::MIN as f64 && integral < i64::MAX as f64).then_some(integral as i64)
+    }
+
+    fn checked_add(self, rhs: Self) -> Option<Self> { Some(self + rhs).filter(|result| result.is_finite()) }
+    fn checked_sub(self, rhs: Self) -> Option<Self> { Some(self - rhs).filter(|result| result.is_finite()) }
+    fn checked_mul(self, rhs: Self) -> Option<Self> { Some(self * rhs).filter(|result| result.is_finite()) }
+    fn checked_div(self, rhs: Self) -> Option<Self> { Some(self / rhs).filter(|result| result.is_finite()) }
+
+    fn wrapping_add(self, rhs: Self) -> Self { self.saturating_add(rhs) }
+    fn wrapping_sub(self, rhs: Self) -> Self { self.saturating_sub(rhs) }
+    fn wrapping_mul(self, rhs: Self) -> Self { self.saturating_mul(rhs) }
+    fn wrapping_div(self, rhs: Self) -> Self { self.saturating_div(rhs) }
+
+    fn saturating_add(self, rhs: Self) -> Self { (self + rhs).clamp(f64::MIN, f64::MAX) }
+    fn saturating_sub(self, rhs: Self) -> Self { (self - rhs).clamp(f64::MIN, f64::MAX) }
+    fn saturating_mul(self, rhs: Self) -> Self { (self * rhs).clamp(f64::MIN, f64::MAX) }
+    fn saturating_div(self, rhs: Self) -> Self { (self / rhs).clamp(f64::MIN, f64::MAX) }
+
+
+    fn sign(&self) -> Self {
+        if *self == 0.0 { 0.0 } else { self.signum() }
+    }
+
+    fn format(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let magnitude = self.abs();
+        if *self == 0.0 {
+            // Also avoids printing the negative zero as "-0"
+            write!(f, "0")
+        } else if !(1e-6..1e15).contains(&magnitude) {
+            write!(f, "{:e}", self)
+        } else {
+            write!(f, "{}", self)
+        }
+    }
+}
+
+/// Big integers never overflow, so all the modes of the arithmetic are the same
+#[cfg(feature = "number-big")]
+impl Numeric for BigInt {
+    fn zero() -> Self { BigInt::default() }
+    fn one() -> Self { BigInt::from(1) }
+    fn limits() -> Option<(Self, Self)> { None }
+
+    fn parse_literal(text: &str) -> Result<Self, ErrorKind> {
+        text.parse()
+            .map_err(|_| ErrorKind::NumberParseError(std::num::IntErrorKind::InvalidDigit))
+    }
+
+    fn from_i64(value: i64) -> Option<Self> {
+        Some(BigInt::from(value))
+    }
+
+    fn to_i64(&self) -> Option<i64> {
+        i64::try_from(self).ok()
+    }
+
+    fn checked_add(self, rhs: Self) -> Option<Self> { Some(&self + &rhs) }
+    fn checked_sub(self, rhs: Self) -> Option<Self> { Some(&self - &rhs) }
+    fn checked_mul(self, rhs: Self) -> Option<Self> { Some(&self * &rhs) }
+    fn checked_div(self, rhs: Self) -> Option<Self> { Some(BigInt::div(&self, &rhs)) }
+
+    fn wrapping_add(self, rhs: Self) -> Self { &self + &rhs }
+    fn wrapping_sub(self, rhs: Self) -> Self { &self - &rhs }
+    fn wrapping_mul(self, rhs: Self) -> Self { &self * &rhs }
+    fn wrapping_div(self, rhs: Self) -> Self { BigInt::div(&self, &rhs) }
+
+    fn saturating_add(self, rhs: Self) -> Self { &self + &rhs }
+    fn saturating_sub(self, rhs: Self) -> Self { &self - &rhs }
+    fn saturating_mul(self, rhs: Self) -> Self { &self * &rhs }
+    fn saturating_div(self, rhs: Self) -> Self { BigInt::div(&self, &rhs) }
+
+
+    fn sign(&self) -> Self {
+        match (self.is_zero(), self.is_negative()) {
+            (true, _) => BigInt::zero(),
+            (false, true) => BigInt::from(-1),
+            (false, false) => BigInt::one(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Numeric;
+
+    struct Formatted<T: Numeric>(T);
+
+    impl<T: Numeric> std::fmt::Display for Formatted<T> {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            self.0.format(f)
+        }
+    }
+
+    #[test]
+    fn test_integers() {
+        assert_eq!(i16::parse_literal("32767").ok(), Some(i16::MAX));
+        assert!(i16::parse_literal("32768").is_err());
+        assert!(i32::parse_literal("3.14").is_err());
+        assert_eq!(i64::parse_literal("-5").ok(), Some(-5));
+        assert_eq!(i16::from_i64(1 << 20), None);
+        assert_eq!(i32::parse_literal("32768").ok(), Some(32768));
+        assert_eq!(Numeric::checked_mul(65536i32, 65536), None);
+        assert_eq!(Numeric::checked_mul(1i64 << 32, 1 << 16), Some(1 << 48));
+        assert_eq!(Numeric::checked_mul(1i64 << 48, 1 << 16), None);
+        assert_eq!(i64::limits(), Some((i64::MIN, i64::MAX)));
+        assert_eq!(Numeric::sign(&-7i32), -1);
+        assert_eq!(Formatted(-42i64).to_string(), "-42");
+    }
+
+    #[test]
+    #[cfg(feature = "number-big")]
+    fn test_big_integers() {
+        use crate::tiny_basic::big_int::BigInt;
+
+        let number = BigInt::parse_literal("123456789012345678901234567890").unwrap();
+        assert_eq!(Numeric::checked_mul(number.clone(), number.clone()).unwrap().to_string(), "15241578753238836750495351562536198787501905199875019052100");
+        assert_eq!(number.to_i64(), None);
+        let big = |text| BigInt::parse_literal(text).unwrap();
+        assert_eq!(Numeric::checked_add(big("99999999999999999999"), BigInt::one()), Some(big("100000000000000000000")));
+        assert_eq!(Numeric::wrapping_mul(big("-32768"), big("65536")), big("-2147483648"));
+        assert_eq!(Numeric::checked_div(big("265252859812191058636308480000000"), big("1000000000000000000000")), Some(big("265252859812")));
+        assert_eq!(Numeric::sign(&BigInt::from(-5)), BigInt::from(-1));
+        assert!(BigInt::parse_literal("3.14").is_err());
+        assert!(BigInt::limits().is_none());
+    }
+
+    #[test]
+    fn test_floating_point() {
+        assert_eq!(f64::parse_literal("2.75").ok(), Some(2.75));
+        assert_eq!(f64::parse_literal("10.").ok(), Some(10.0));
+        assert!(f64::parse_literal(&"9".repeat(400)).is_err());
+        assert_eq!(Numeric::checked_mul(f64::MAX, 2.0), None);
+        assert_eq!(Numeric::wrapping_add(f64::MAX, f64::MAX), f64::MAX);
+        assert_eq!(2.9f64.to_i64(), Some(2));
+        assert_eq!((-2.9f64).to_i64(), Some(-2));
+        assert_eq!(f64::INFINITY.to_i64(), None);
+        assert_eq!(Numeric::sign(&0.0f64), 0.0);
+        assert_eq!(Formatted(2.75f64).to_string(), "2.75");
+        assert_eq!(Formatted(-0.0f64).to_string(), "0");
+        assert_eq!(Formatted(1e300f64).to_string(), "1e300");
+    }
+}
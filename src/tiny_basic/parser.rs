@@ -0,0 +1,491 @@
+/*
+    Tiny BASIC interpreter written in Rust
+    Copyright (C) 2025 Artyom Makarov
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+//! Builds the tree of a line from its tokens, following the grammar of the design note.
+
+use ascii::{AsciiChar, AsciiStr, AsciiString};
+
+use crate::tiny_basic;
+use crate::tiny_basic::ast::{ArrayDeclaration, Expression, ExpressionKind, ForLoop, Statement, StatementKind, Target};
+use crate::tiny_basic::char_stream::{ArithmeticOperator, Keyword, Statement as StatementKeyword};
+use crate::tiny_basic::error::{Error as TinyBasicError, ErrorContext, ErrorKind as TinyBasicErrorKind};
+use crate::tiny_basic::lexer::{self, TokenStream};
+use crate::tiny_basic::number::Numeric;
+use crate::tiny_basic::types;
+
+/// Parses the statements of a line separated by colons.
+/// The line number is only used for the errors.
+pub fn parse_line(line: &AsciiStr, line_number: Option<types::LineIndex>) -> tiny_basic::Result<'_, Vec<Statement>> {
+    let tokens = lexer::tokenize(line).map_err(|error| error.set_line_number(line_number))?;
+    let parser = Parser { line_number };
+    let mut statements = Vec::new();
+    parser.statements(&mut TokenStream::new(line, &tokens), &mut statements)?;
+    Ok(statements)
+}
+
+struct Parser {
+    line_number: Option<types::LineIndex>
+}
+
+impl<'line_source> Parser {
+    fn error(&self, position: &TokenStream<'_, 'line_source>, kind: TinyBasicErrorKind) -> TinyBasicError<'line_source> {
+        TinyBasicError::from_context(position, kind, self.line_number)
+    }
+
+    fn statements(&self, stmt: &mut TokenStream<'_, 'line_source>, statements: &mut Vec<Statement>) -> tiny_basic::Result<'line_source, ()> {
+        loop {
+            self.statement(stmt, statements)?;
+            if stmt.consume_comment().is_some() {
+                statements.push(Statement { kind: StatementKind::Rem, end: stmt.get_location() });
+            }
+            if stmt.consume_char(AsciiChar::Colon).is_none() {
+                break;
+            }
+        }
+        stmt
+            .is_empty()
+            .then_some(())
+            .ok_or(self.error(stmt, TinyBasicErrorKind::UnexpectedTokensAtEndOfLine))
+    }
+
+    /// Parses a statement, followed by the statements after THEN if it is an IF
+    fn statement(&self, stmt: &mut TokenStream<'_, 'line_source>, statements: &mut Vec<Statement>) -> tiny_basic::Result<'line_source, ()> {
+        if stmt.consume_comment().is_some() {
+            statements.push(Statement { kind: StatementKind::Rem, end: stmt.get_location() });
+            return Ok(());
+        }
+
+        let position = *stmt;
+        let statement = stmt
+            .consume_statement()
+            .ok_or(self.error(&position, TinyBasicErrorKind::ExpectedStatement))?;
+
+        let kind = match statement {
+            StatementKeyword::Print => StatementKind::Print(self.list(stmt, Self::expression)?),
+            StatementKeyword::If => {
+                let lhs = self.expression(stmt)?;
+                let operator = stmt
+                    .consume_relop()
+                    .ok_or(self.error(stmt, TinyBasicErrorKind::ExpectedRelationalOperator))?;
+                let rhs = self.expression(stmt)?;
+                self.keyword(stmt, Keyword::Then)?;
+                statements.push(Statement { kind: StatementKind::If { lhs, operator, rhs }, end: stmt.get_location() });
+                return self.statements(stmt, statements);
+            },
+            StatementKeyword::Goto => StatementKind::Goto(self.expression(stmt)?),
+            StatementKeyword::Gosub => StatementKind::Gosub(self.expression(stmt)?),
+            StatementKeyword::Let => {
+                let target = self.target(stmt)?;
+                self.char(stmt, AsciiChar::Equal, '=')?;
+                StatementKind::Let(target, self.expression(stmt)?)
+            },
+            StatementKeyword::Return => StatementKind::Return,
+            StatementKeyword::End => StatementKind::End,
+            StatementKeyword::Stop => StatementKind::Stop,
+            StatementKeyword::Input => StatementKind::Input(self.list(stmt, |parser, stmt| {
+                let start = stmt.get_location();
+                let target = parser.target(stmt)?;
+                let text = stmt.get_stream()[start..stmt.get_location()].trim_end().to_owned();
+                Ok((target, text))
+            })?),
+            StatementKeyword::For => {
+                let variable_location = stmt.get_location();
+                let variable = self.variable(stmt)?;
+                self.char(stmt, AsciiChar::Equal, '=')?;
+                let start = self.expression(stmt)?;
+                self.keyword(stmt, Keyword::To)?;
+                let limit = self.expression(stmt)?;
+                let mut lookahead = *stmt;
+                let step = if lookahead.consume_keyword() == Some(Keyword::Step) {
+                    *stmt = lookahead;
+                    Some(self.expression(stmt)?)
+                } else {
+                    None
+                };
+                StatementKind::For(ForLoop { variable, variable_location, start, limit, step })
+            },
+            StatementKeyword::Next => StatementKind::Next(stmt.consume_var().map(ToOwned::to_owned)),
+            StatementKeyword::Dim => StatementKind::Dim(self.list(stmt, |parser, stmt| {
+                let location = stmt.get_location();
+                let name = parser.variable(stmt)?;
+                parser.char(stmt, AsciiChar::ParenOpen, '(')?;
+                let bounds = parser.arguments(stmt)?;
+                Ok(ArrayDeclaration { name, bounds, location })
+            })?),
+            StatementKeyword::Randomize => {
+                if stmt.is_end_of_statement() {
+                    StatementKind::Randomize(None)
+                } else {
+                    StatementKind::Randomize(Some(self.expression(stmt)?))
+                }
+            },
+            StatementKeyword::Rem => {
+                stmt.flush();
+                StatementKind::Rem
+            },
+        };
+        statements.push(Statement { kind, end: stmt.get_location() });
+        Ok(())
+    }
+
+    /// Comma separated list of the items accepted by `item`
+    fn list<T, F>(&self, stmt: &mut TokenStream<'_, 'line_source>, item: F) -> tiny_basic::Result<'line_source, Vec<T>>
+    where F: Fn(&Self, &mut TokenStream<'_, 'line_source>) -> tiny_basic::Result<'line_source, T> {
+        let mut items = vec![item(self, stmt)?];
+        while stmt.consume_char(AsciiChar::Comma).is_some() {
+            items.push(item(self, stmt)?);
+        }
+        Ok(items)
+    }
+
+    fn keyword(&self, stmt: &mut TokenStream<'_, 'line_source>, keyword: Keyword) -> tiny_basic::Result<'line_source, ()> {
+        let mut keyword_end = *stmt;
+        if keyword_end.consume_keyword() == Some(keyword) {
+            *stmt = keyword_end;
+            Ok(())
+        } else {
+            Err(self.error(stmt, TinyBasicErrorKind::ExpectedKeyword))
+        }
+    }
+
+    fn char(&self, stmt: &mut TokenStream<'_, 'line_source>, ch: AsciiChar, expected: char) -> tiny_basic::Result<'line_source, ()> {
+        stmt
+            .consume_char(ch)
+            .ok_or(self.error(stmt, TinyBasicErrorKind::Expected(expected)))
+    }
+
+    fn variable(&self, stmt: &mut TokenStream<'_, 'line_source>) -> tiny_basic::Result<'line_source, AsciiString> {
+        stmt
+            .consume_var()
+            .map(ToOwned::to_owned)
+            .ok_or(self.error(stmt, TinyBasicErrorKind::ExpectedVariableName))
+    }
+
+    /// Variable or array element
+    fn target(&self, stmt: &mut TokenStream<'_, 'line_source>) -> tiny_basic::Result<'line_source, Target> {
+        let location = stmt.get_location();
+        let name = self.variable(stmt)?;
+        let subscripts = if stmt.consume_char(AsciiChar::ParenOpen).is_some() {
+            Some(self.arguments(stmt)?)
+        } else {
+            None
+        };
+        Ok(Target { name, subscripts, location })
+    }
+
+    /// Subscripts or function arguments after the opening parenthesis
+    fn arguments(&self, stmt: &mut TokenStream<'_, 'line_source>) -> tiny_basic::Result<'line_source, Vec<Expression>> {
+        let arguments = self.list(stmt, Self::expression)?;
+        self.char(stmt, AsciiChar::ParenClose, ')')?;
+        Ok(arguments)
+    }
+
+    /// Parses an expression by precedence climbing:
+    ///
+    /// expression ::= (+|-|ε) term ((+|-) term)*
+    ///
+    /// where the operators of the same precedence are left-associative.
+    /// As in the DDJ grammar, the sign which starts an expression applies to the whole
+    /// first term, so `-2*16384` overflows with 16-bit numbers. A sign may also start
+    /// a factor, so `2*-3` is accepted as well.
+    fn expression(&self, stmt: &mut TokenStream<'_, 'line_source>) -> tiny_basic::Result<'line_source, Expression> {
+        let location = stmt.get_location();
+        match Self::sign(stmt) {
+            Some(operator) => {
+                let term = self.binary_expression(stmt, ArithmeticOperator::Multiply.precedence())?;
+                let first_term = Expression { kind: ExpressionKind::Unary(operator, Box::new(term)), location };
+                self.operators(stmt, first_term, 0)
+            },
+            None => self.binary_expression(stmt, 0),
+        }
+    }
+
+    /// Unary plus or minus
+    fn sign(stmt: &mut TokenStream<'_, 'line_source>) -> Option<ArithmeticOperator> {
+        stmt
+            .consume_char_if(|ch| matches!(ch, AsciiChar::Plus | AsciiChar::Minus))
+            .map(|sign| match sign {
+                AsciiChar::Minus => ArithmeticOperator::Subtract,
+                _ => ArithmeticOperator::Add,
+            })
+    }
+
+    fn binary_expression(&self, stmt: &mut TokenStream<'_, 'line_source>, min_precedence: u8) -> tiny_basic::Result<'line_source, Expression> {
+        let lhs = self.factor(stmt)?;
+        self.operators(stmt, lhs, min_precedence)
+    }
+
+    /// Applies the operators of at least `min_precedence` which follow `lhs`
+    fn operators(&self, stmt: &mut TokenStream<'_, 'line_source>, mut lhs: Expression, min_precedence: u8) -> tiny_basic::Result<'line_source, Expression> {
+        loop {
+            let operator_location = stmt.get_location();
+            let mut lookahead = *stmt;
+            let operator = match lookahead.consume_arithmetic_operator() {
+                Some(operator) if operator.precedence() >= min_precedence => operator,
+                _ => return Ok(lhs),
+            };
+            *stmt = lookahead;
+
+            let rhs = self.binary_expression(stmt, operator.precedence() + 1)?;
+            let location = lhs.location;
+            lhs = Expression {
+                kind: ExpressionKind::Binary { operator, operator_location, lhs: Box::new(lhs), rhs: Box::new(rhs) },
+                location
+            };
+        }
+    }
+
+    fn factor(&self, stmt: &mut TokenStream<'_, 'line_source>) -> tiny_basic::Result<'line_source, Expression> {
+        let position = *stmt;
+        let location = stmt.get_location();
+        let kind = if let Some(operator) = Self::sign(stmt) {
+            ExpressionKind::Unary(operator, Box::new(self.factor(stmt)?))
+        } else if let Some(name) = stmt.consume_var() {
+            if stmt.consume_char(AsciiChar::ParenOpen).is_some() {
+                ExpressionKind::Call(name.to_owned(), self.arguments(stmt)?)
+            } else {
+                ExpressionKind::Variable(name.to_owned())
+            }
+        } else if let Some(number) = stmt.consume_number() {
+            let number = types::Number::parse_literal(number.as_str())
+                .map_err(|kind| self.error(&position, kind))?;
+            ExpressionKind::Number(number)
+        } else if let Some(string) = stmt.consume_string() {
+            ExpressionKind::String(string.to_owned())
+        } else if stmt.consume_char(AsciiChar::ParenOpen).is_some() {
+            // The parentheses are a part of the expression, e.g. for an error about its type
+            let expression = self.expression(stmt)?;
+            self.char(stmt, AsciiChar::ParenClose, ')')?;
+            return Ok(Expression { location, ..expression });
+        } else {
+            return Err(self.error(stmt, TinyBasicErrorKind::FactorCouldNotBeParsed));
+        };
+        Ok(Expression { kind, location })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ascii::AsciiStr;
+
+    use crate::tiny_basic::ast::{Expression, ExpressionKind, StatementKind};
+    use crate::tiny_basic::char_stream::ArithmeticOperator;
+    use crate::tiny_basic::error::ErrorKind;
+
+    use super::parse_line;
+
+    fn parse_error(line: &str) -> (ErrorKind, Option<usize>) {
+        let error = parse_line(AsciiStr::from_ascii(line).unwrap(), None).err().unwrap();
+        (error.get_kind(), error.get_location())
+    }
+
+    fn parse(line: &str) -> Vec<StatementKind> {
+        parse_line(AsciiStr::from_ascii(line).unwrap(), None)
+            .unwrap()
+            .into_iter()
+            .map(|statement| statement.kind)
+            .collect()
+    }
+
+    /// Renders an expression with the order of evaluation made explicit
+    fn render(expression: &Expression) -> String {
+        match &expression.kind {
+            ExpressionKind::Number(number) => number.to_string(),
+            ExpressionKind::String(string) => format!("\"{}\"", string),
+            ExpressionKind::Variable(name) => name.to_string(),
+            ExpressionKind::Call(name, arguments) => {
+                let arguments: Vec<_> = arguments.iter().map(render).collect();
+                format!("{}({})", name, arguments.join(", "))
+            },
+            ExpressionKind::Unary(operator, operand) => format!("{}{}", symbol(*operator), render(operand)),
+            ExpressionKind::Binary { operator, lhs, rhs, .. } => format!("({} {} {})", render(lhs), symbol(*operator), render(rhs)),
+        }
+    }
+
+    fn symbol(operator: ArithmeticOperator) -> char {
+        match operator {
+            ArithmeticOperator::Add => '+',
+            ArithmeticOperator::Subtract => '-',
+            ArithmeticOperator::Multiply => '*',
+            ArithmeticOperator::Divide => '/',
+        }
+    }
+
+    fn expression(text: &str) -> String {
+        match &parse(&format!("PRINT {}", text))[..] {
+            [StatementKind::Print(expressions)] => render(&expressions[0]),
+            statements => panic!("Unexpected statements: {:?}", statements),
+        }
+    }
+
+    #[test]
+    fn test_precedence() {
+        assert_eq!(expression("1 + 2 * 3 - 4"), "((1 + (2 * 3)) - 4)");
+        assert_eq!(expression("8 / 4 / 2"), "((8 / 4) / 2)");
+        assert_eq!(expression("(1 + 2) * -A"), "((1 + 2) * -A)");
+        assert_eq!(expression("MAX(A-B, C(1))"), "MAX((A - B), C(1))");
+    }
+
+    #[test]
+    fn test_operator_chains() {
+        assert_eq!(expression("2*3*4"), "((2 * 3) * 4)");
+        assert_eq!(expression("100/10/5"), "((100 / 10) / 5)");
+        assert_eq!(expression("8/2*4"), "((8 / 2) * 4)");
+        assert_eq!(expression("10-2-3"), "((10 - 2) - 3)");
+        assert_eq!(expression("2+3*4-6/2"), "((2 + (3 * 4)) - (6 / 2))");
+        assert_eq!(expression("A/B*C - A - B - C"), "(((((A / B) * C) - A) - B) - C)");
+        assert_eq!(expression("((7))"), "7");
+        assert_eq!(expression("2*-3"), "(2 * -3)");
+        assert_eq!(expression("4--1"), "(4 - -1)");
+    }
+
+    #[test]
+    fn test_sign_applies_to_first_term() {
+        assert_eq!(expression("-2*16384"), "-(2 * 16384)");
+        assert_eq!(expression("-2*-3"), "-(2 * -3)");
+        assert_eq!(expression("-(2+3)*2"), "-((2 + 3) * 2)");
+        assert_eq!(expression("+A/B-C"), "(+(A / B) - C)");
+        assert_eq!(expression("-A-B*C"), "(-A - (B * C))");
+        assert_eq!(expression("(-2*3)"), "-(2 * 3)");
+        assert_eq!(expression("MAX(-2*A, -B)"), "MAX(-(2 * A), -B)");
+    }
+
+    #[test]
+    fn test_locations() {
+        let statements = parse_line(AsciiStr::from_ascii("LET A = B + (C * 2)").unwrap(), None).unwrap();
+        let StatementKind::Let(target, value) = &statements[0].kind else {
+            panic!("Expected LET");
+        };
+        assert_eq!(target.location, 4);
+        assert_eq!(value.location, 8);
+        let ExpressionKind::Binary { operator_location, rhs, .. } = &value.kind else {
+            panic!("Expected a binary expression");
+        };
+        assert_eq!(*operator_location, 10);
+        // The parentheses belong to the expression
+        assert_eq!(rhs.location, 12);
+        assert_eq!(statements[0].end, 19);
+    }
+
+    #[test]
+    fn test_statements_separated_by_colons() {
+        assert!(matches!(parse("LET A = 1 : PRINT A : GOTO 30")[..], [
+            StatementKind::Let(..),
+            StatementKind::Print(_),
+            StatementKind::Goto(_)
+        ]));
+        assert!(matches!(parse("END:PRINT 1")[..], [StatementKind::End, StatementKind::Print(_)]));
+
+        let (kind, location) = parse_error("PRINT A : PRINT A B");
+        assert!(matches!(kind, ErrorKind::UnexpectedTokensAtEndOfLine));
+        assert_eq!(location, Some(18));
+        assert!(matches!(parse_error("PRINT A :").0, ErrorKind::ExpectedStatement));
+    }
+
+    #[test]
+    fn test_for_and_next() {
+        let statements = parse("FOR I = 10 TO 1 STEP -4 : NEXT I : NEXT");
+        let [StatementKind::For(for_loop), StatementKind::Next(Some(variable)), StatementKind::Next(None)] = &statements[..] else {
+            panic!("Unexpected statements: {:?}", statements);
+        };
+        assert_eq!((for_loop.variable.as_str(), for_loop.variable_location), ("I", 4));
+        assert_eq!((render(&for_loop.start), render(&for_loop.limit)), ("10".to_owned(), "1".to_owned()));
+        assert_eq!(for_loop.step.as_ref().map(render).as_deref(), Some("-4"));
+        assert_eq!(variable.as_str(), "I");
+
+        let [StatementKind::For(for_loop)] = &parse("FOR I = 1 TO N")[..] else {
+            panic!("Expected FOR");
+        };
+        assert!(for_loop.step.is_none());
+
+        assert!(matches!(parse_error("FOR I = 1").0, ErrorKind::ExpectedKeyword));
+        assert!(matches!(parse_error("FOR I = 1 TO 2 STEP").0, ErrorKind::FactorCouldNotBeParsed));
+        assert!(matches!(parse_error("FOR 1 = 1 TO 2").0, ErrorKind::ExpectedVariableName));
+        assert!(matches!(parse_error("FOR I 1 TO 2").0, ErrorKind::Expected('=')));
+    }
+
+    #[test]
+    fn test_arrays() {
+        let [StatementKind::Dim(arrays)] = &parse("DIM A(3), M(2, N + 1)")[..] else {
+            panic!("Expected DIM");
+        };
+        let arrays: Vec<_> = arrays
+            .iter()
+            .map(|array| (array.name.as_str(), array.bounds.iter().map(render).collect::<Vec<_>>(), array.location))
+            .collect();
+        assert_eq!(arrays, [("A", vec!["3".to_owned()], 4), ("M", vec!["2".to_owned(), "(N + 1)".to_owned()], 10)]);
+
+        let [StatementKind::Let(target, value)] = &parse("LET A(I, 2) = A(1)")[..] else {
+            panic!("Expected LET");
+        };
+        assert_eq!(target.subscripts.as_ref().map(|subscripts| subscripts.len()), Some(2));
+        assert_eq!(render(value), "A(1)");
+
+        assert!(matches!(parse_error("DIM A").0, ErrorKind::Expected('(')));
+        assert!(matches!(parse_error("DIM A(1").0, ErrorKind::Expected(')')));
+        assert!(matches!(parse_error("DIM A()").0, ErrorKind::FactorCouldNotBeParsed));
+    }
+
+    #[test]
+    fn test_strings() {
+        let [StatementKind::Let(target, value)] = &parse("LET B$ = A$ + \" \" + \"BASIC\"")[..] else {
+            panic!("Expected LET");
+        };
+        assert_eq!(target.name.as_str(), "B$");
+        assert_eq!(render(value), "((A$ + \" \") + \"BASIC\")");
+
+        let [StatementKind::Input(targets)] = &parse("INPUT N$, N$(1)")[..] else {
+            panic!("Expected INPUT");
+        };
+        let prompts: Vec<_> = targets.iter().map(|(_, prompt)| prompt.as_str()).collect();
+        assert_eq!(prompts, ["N$", "N$(1)"]);
+    }
+
+    #[test]
+    fn test_comments() {
+        for line in ["REM COUNT TO 2", "REM: PRINT 0", "' PRINT 0", "'"] {
+            assert!(matches!(parse(line)[..], [StatementKind::Rem]), "{}", line);
+        }
+        for line in ["PRINT 1 ' PRINT 0", "PRINT 2 : REM PRINT 0", "PRINT 3 : ' PRINT 0"] {
+            assert!(matches!(parse(line)[..], [StatementKind::Print(_), StatementKind::Rem]), "{}", line);
+        }
+        let [StatementKind::Print(expressions), StatementKind::Rem] = &parse("PRINT \"'\" '")[..] else {
+            panic!("Expected PRINT and a comment");
+        };
+        assert_eq!(render(&expressions[0]), "\"'\"");
+
+        // REM is only a keyword as a whole word
+        let [StatementKind::Let(target, _), StatementKind::Print(expressions)] = &parse("LET REMAINDER = 7 : PRINT REMARK")[..] else {
+            panic!("Expected LET and PRINT");
+        };
+        assert_eq!(target.name.as_str(), "REMAINDER");
+        assert_eq!(render(&expressions[0]), "REMARK");
+    }
+
+    #[test]
+    fn test_statements_after_then_follow_if() {
+        let statements = parse("IF A = 1 THEN PRINT A : GOTO 10 ' done");
+        assert!(matches!(statements[..], [
+            StatementKind::If { .. },
+            StatementKind::Print(_),
+            StatementKind::Goto(_),
+            StatementKind::Rem
+        ]));
+        assert!(matches!(parse("REM : PRINT")[..], [StatementKind::Rem]));
+    }
+}
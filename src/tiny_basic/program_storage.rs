@@ -1,59 +1,231 @@
 use std::collections::BTreeMap;
+use std::ops::{Bound, RangeInclusive};
+use std::sync::atomic::{AtomicU64, Ordering};
 
-use ascii::{AsciiStr, AsciiString};
+use ascii::{AsAsciiStr, AsciiStr, AsciiString};
 
+use crate::tiny_basic;
+use crate::tiny_basic::ast;
+use crate::tiny_basic::char_stream::AsciiCharStream;
+use crate::tiny_basic::code_line::Line;
+use crate::tiny_basic::error::{Error, ErrorKind};
+use crate::tiny_basic::parser;
 use crate::tiny_basic::types;
 
+/// Text of a line together with its tree, which is parsed once when the line is stored
+struct StoredLine {
+    source: AsciiString,
+    /// `None` if the line has a syntax error
+    statements: Option<Vec<ast::Statement>>
+}
+
 pub struct ProgramStorage {
-    storage: BTreeMap<types::LineIndex, AsciiString>
+    storage: BTreeMap<types::LineIndex, StoredLine>,
+    revision: u64
+}
+
+/// A revision which no program has had yet
+fn new_revision() -> u64 {
+    static LAST_REVISION: AtomicU64 = AtomicU64::new(0);
+    LAST_REVISION.fetch_add(1, Ordering::Relaxed) + 1
+}
+
+impl Default for ProgramStorage {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl ProgramStorage {
     pub fn new() -> Self {
         Self {
             storage: BTreeMap::new(),
+            revision: new_revision()
+        }
+    }
+
+    /// Builds a program from source text, one numbered line per text line.
+    /// Blank lines are skipped, every other line must start with a line index.
+    pub fn load(source: &str) -> tiny_basic::Result<'_, Self> {
+        let source = source
+            .as_ascii_str()
+            .map_err(|error| Error::from(ErrorKind::from(error)))?;
+
+        let mut program = Self::new();
+        for (_, line) in Self::parse_source(source) {
+            let (index, contents) = line?;
+            program.insert_line(index, contents);
         }
+        Ok(program)
+    }
+
+    /// Splits source text into numbered lines, yielding each line together with
+    /// its position in the text (counting from 1). Blank lines are skipped.
+    pub fn parse_source(source: &AsciiStr) -> impl Iterator<Item = (usize, tiny_basic::Result<'_, (types::LineIndex, &AsciiStr)>)> {
+        source
+            .lines()
+            .enumerate()
+            .map(|(i, text_line)| (i + 1, text_line.trim()))
+            .filter(|(_, text_line)| !text_line.is_empty())
+            .map(|(i, text_line)| {
+                let context = AsciiCharStream::from_ascii_str(text_line);
+                let line = Line::try_from(text_line)
+                    .map_err(|kind| Error::from_context(&context, kind, None))
+                    .and_then(|line| match line.index {
+                        Some(index) => Ok((index, line.statement)),
+                        None => Err(Error::from_context(&context, ErrorKind::ExpectedLineIndex, None)),
+                    });
+                (i, line)
+            })
+    }
+
+    /// Renders the program as source text accepted by [`ProgramStorage::load`]
+    pub fn to_source(&self) -> String {
+        self.storage
+            .iter()
+            .map(|(i, line)| format!("{} {}\n", i, line.source))
+            .collect()
     }
 
     pub fn clear(&mut self) {
         self.storage.clear();
+        self.revision = new_revision();
+    }
+
+    /// Changes whenever the program is edited. Different programs never have the same revision,
+    /// so a program which was replaced, e.g. by LOAD, is not mistaken for the old one.
+    pub fn revision(&self) -> u64 {
+        self.revision
     }
 
     pub fn get_line(&self, line_index: types::LineIndex) -> Option<&AsciiStr> {
         self
             .storage
             .get(&line_index)
-            .map(|line| &**line)
+            .map(|line| &*line.source)
     }
 
-    pub fn get_following_line_index(&self, line_index: types::LineIndex) -> Option<types::LineIndex> {
-        let pivot_index = line_index;
-        let next_line_index = self
-            .storage
-            .keys()
-            .skip_while(|line_index| **line_index != pivot_index)
-            .nth(1);
+    /// Returns the parsed statements of a line, or its syntax error
+    pub fn get_statements(&self, line_index: types::LineIndex) -> Option<tiny_basic::Result<'_, &[ast::Statement]>> {
+        let line = self.storage.get(&line_index)?;
+        match &line.statements {
+            Some(statements) => Some(Ok(statements)),
+            // The error is not kept, the line is parsed again to get it
+            None => Some(Err(parser::parse_line(&line.source, Some(line_index)).expect_err("Line should have a syntax error"))),
+        }
+    }
 
-        next_line_index.and_then(|next_line_index| {
-            Some(*next_line_index)
-        })
+    /// Returns the line which follows an existing line, or `None` if the line is the last one
+    /// or does not exist, so that a GOTO to a missing line ends the program
+    pub fn get_following_line_index(&self, line_index: types::LineIndex) -> Option<types::LineIndex> {
+        if !self.storage.contains_key(&line_index) {
+            return None;
+        }
+        self.storage
+            .range((Bound::Excluded(line_index), Bound::Unbounded))
+            .next()
+            .map(|(next_line_index, _)| *next_line_index)
     }
 
     pub fn erase_line(&mut self, line_index: types::LineIndex) {
         self.storage.remove(&line_index);
+        self.revision = new_revision();
     }
 
     pub fn insert_line(&mut self, line_index: types::LineIndex, line_contents: &AsciiStr) {
-        self.storage.insert(line_index, line_contents.to_owned());
+        let statements = parser::parse_line(line_contents, Some(line_index)).ok();
+        self.storage.insert(line_index, StoredLine { source: line_contents.to_owned(), statements });
+        self.revision = new_revision();
     }
 
     pub fn get_first_line_index(&self) -> Option<types::LineIndex> {
         self.storage
             .first_key_value()
-            .and_then(|(first_line_index, _)| Some(*first_line_index))
+            .map(|(first_line_index, _)| *first_line_index)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&types::LineIndex, &AsciiStr)> {
+        self.storage
+            .iter()
+            .map(|(i, line)| (i, &*line.source))
+    }
+
+    /// The lines within the range, none if it starts after its end
+    pub fn range(&self, lines: RangeInclusive<types::LineIndex>) -> impl Iterator<Item = (&types::LineIndex, &AsciiStr)> {
+        (lines.start() <= lines.end())
+            .then(|| self.storage.range(lines))
+            .into_iter()
+            .flatten()
+            .map(|(i, line)| (i, &*line.source))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tiny_basic::{error::ErrorKind, types::LineIndex};
+
+    use super::ProgramStorage;
+
+    #[test]
+    fn test_get_following_line_index() {
+        let program = ProgramStorage::load("10 PRINT 1\n20 PRINT 2\n40 PRINT 4").unwrap();
+        let line = |i| LineIndex::try_from(i).unwrap();
+        assert_eq!(program.get_following_line_index(line(10)), Some(line(20)));
+        assert_eq!(program.get_following_line_index(line(20)), Some(line(40)));
+        assert_eq!(program.get_following_line_index(line(40)), None);
+        // A missing line has no following line, even if there are lines after it
+        assert_eq!(program.get_following_line_index(line(30)), None);
+        assert_eq!(program.get_following_line_index(line(5)), None);
+    }
+
+    #[test]
+    fn test_range() {
+        let program = ProgramStorage::load("10 PRINT 1\n20 PRINT 2\n40 PRINT 4").unwrap();
+        let line = |i| LineIndex::try_from(i).unwrap();
+        let listed = |lines| program.range(lines).map(|(i, _)| i16::from(*i)).collect::<Vec<_>>();
+        assert_eq!(listed(line(20)..=line(20)), [20]);
+        assert_eq!(listed(line(15)..=line(40)), [20, 40]);
+        assert_eq!(listed(LineIndex::FIRST..=line(30)), [10, 20]);
+        assert_eq!(listed(line(30)..=LineIndex::LAST), [40]);
+        assert_eq!(listed(line(30)..=line(30)), []);
+        assert_eq!(listed(line(40)..=line(10)), []);
+    }
+
+    #[test]
+    fn test_load() {
+        let program = ProgramStorage::load("10 LET A = 1\n\n20 PRINT A\n").unwrap();
+        let lines: Vec<_> = program
+            .iter()
+            .map(|(i, line)| (*i, line.as_str()))
+            .collect();
+        assert_eq!(lines, [
+            (LineIndex::try_from(10).unwrap(), "LET A = 1"),
+            (LineIndex::try_from(20).unwrap(), "PRINT A")
+        ]);
     }
 
-    pub fn iter(&self) -> std::collections::btree_map::Iter<'_, types::LineIndex, AsciiString> {
-        self.storage.iter()
+    #[test]
+    fn test_load_without_line_index() {
+        let error = ProgramStorage::load("10 LET A = 1\nPRINT A").err().unwrap();
+        assert!(matches!(error.get_kind(), ErrorKind::ExpectedLineIndex));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_source_round_trip() {
+        let source = "10 PRINT \"HELLO\" ' Greet the user\n15 REM  Forever\n20 GOTO 10\n";
+        assert_eq!(ProgramStorage::load(source).unwrap().to_source(), source);
+    }
+
+    #[test]
+    fn test_parse_source_reports_positions() {
+        let source = ascii::AsciiStr::from_ascii("10 LET A = 1\n\nPRINT A\n40000 END").unwrap();
+        let bad_lines: Vec<_> = ProgramStorage::parse_source(source)
+            .filter_map(|(i, line)| line.err().map(|error| (i, error.get_kind())))
+            .collect();
+        assert_eq!(bad_lines.len(), 2);
+        assert_eq!(bad_lines[0].0, 3);
+        assert!(matches!(bad_lines[0].1, ErrorKind::ExpectedLineIndex));
+        assert_eq!(bad_lines[1].0, 4);
+        assert!(matches!(bad_lines[1].1, ErrorKind::NumberParseError(_)));
+    }
+}
@@ -0,0 +1,105 @@
+/*
+    Tiny BASIC interpreter written in Rust
+    Copyright (C) 2025 Artyom Makarov
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use std::cell::Cell;
+
+/// Pseudo-random number generator behind RND (xorshift64*).
+/// The state is kept in a cell since expressions are evaluated through a shared reference.
+#[derive(Debug)]
+pub struct Prng {
+    state: Cell<u64>
+}
+
+impl Default for Prng {
+    fn default() -> Self {
+        Self::new(Self::DEFAULT_SEED)
+    }
+}
+
+impl Prng {
+    /// Seed used until RANDOMIZE is executed, so that every run of a program
+    /// produces the same numbers
+    pub const DEFAULT_SEED: u64 = 0;
+
+    pub fn new(seed: u64) -> Self {
+        let prng = Self {
+            state: Cell::new(0)
+        };
+        prng.seed(seed);
+        prng
+    }
+
+    pub fn seed(&self, seed: u64) {
+        // Xorshift must not be in the zero state, so the seed is scrambled
+        // with a step of SplitMix64 which maps only one seed to zero
+        let mut z = seed.wrapping_add(0x9E3779B97F4A7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        z ^= z >> 31;
+        self.state.set(if z == 0 { 1 } else { z });
+    }
+
+    pub fn next_u64(&self) -> u64 {
+        let mut x = self.state.get();
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        self.state.set(x);
+        x.wrapping_mul(0x2545F4914F6CDD1D)
+    }
+
+    /// Returns a number uniformly distributed in range [0; bound)
+    pub fn below(&self, bound: u64) -> u64 {
+        assert!(bound > 0, "Bound should be positive");
+        // Values from the incomplete last block are rejected to avoid bias
+        let zone = u64::MAX - u64::MAX % bound;
+        loop {
+            let x = self.next_u64();
+            if x < zone {
+                return x % bound;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Prng;
+
+    #[test]
+    fn test_same_seed_same_sequence() {
+        let a = Prng::new(42);
+        let b = Prng::new(42);
+        for _ in 0..100 {
+            assert_eq!(a.next_u64(), b.next_u64());
+        }
+        a.seed(7);
+        b.seed(8);
+        assert_ne!(a.next_u64(), b.next_u64());
+    }
+
+    #[test]
+    fn test_below() {
+        let prng = Prng::default();
+        let mut seen = [false; 6];
+        for _ in 0..1000 {
+            seen[prng.below(6) as usize] = true;
+        }
+        assert!(seen.iter().all(|seen| *seen));
+    }
+}
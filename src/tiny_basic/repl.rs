@@ -16,19 +16,24 @@
     along with this program.  If not, see <https://www.gnu.org/licenses/>.
 */
 
-use ascii::AsciiStr;
+use ascii::{AsciiChar, AsciiStr};
 
 use crate::tiny_basic::{
     interpreter::Interpreter, 
     code_line::Line, 
     char_stream,
+    engine::Engine,
+    lexer::{self, TokenStream},
     program_storage::ProgramStorage,
+    syntax_check::{self, SyntaxCheck},
     types
 };
 
 use crate::tiny_basic;
+use crate::tiny_basic::error::{Error as TinyBasicError, ErrorKind as TinyBasicErrorKind};
 
-use std::io::stdin;
+use std::io::{stdin, stdout, BufRead, IsTerminal, StdinLock, Stdout, Write};
+use std::ops::RangeInclusive;
 
 macro_rules! unwrap_or_continue {
     ($result:expr) => {
@@ -47,29 +52,73 @@ macro_rules! show_outcome {
         match $result {
             Ok(_) => println!("OK"),
             Err(error) => {
-                eprintln!("{}", error);
+                print_error(&error);
                 continue;
             }
         }
     };
 }
 
+/// Receives a warning of the REPL, e.g. the syntax error of a line stored with [`SyntaxCheck::Warn`]
+pub type WarningHandler = fn(&str);
+
 /// Read, Evaluate, Print, Loop
 pub struct Repl {
     interpreter: Interpreter,
-    program: ProgramStorage
+    program: ProgramStorage,
+    syntax_check: SyntaxCheck,
+    engine: Engine,
+    warning_handler: WarningHandler
+}
+
+impl Default for Repl {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl<'user_input> Repl {
     pub fn new() -> Self {
+        Self::with_program(ProgramStorage::new())
+    }
+
+    pub fn with_program(program: ProgramStorage) -> Self {
         Self {
             interpreter: Interpreter::new(),
-            program: ProgramStorage::new()
+            program,
+            syntax_check: SyntaxCheck::default(),
+            engine: Engine::default(),
+            warning_handler: |_| ()
         }
     }
 
+    /// Sets how the lines are checked when they are entered
+    pub fn set_syntax_check(&mut self, syntax_check: SyntaxCheck) {
+        self.syntax_check = syntax_check;
+    }
+
+    /// Sets what is done with the warnings, which are dropped by default
+    pub fn set_warning_handler(&mut self, handler: WarningHandler) {
+        self.warning_handler = handler;
+    }
+
+    /// Sets the engine which runs the stored program
+    pub fn set_engine(&mut self, engine: Engine) {
+        self.engine = engine;
+    }
+
+    pub fn interpreter_mut(&mut self) -> &mut Interpreter {
+        &mut self.interpreter
+    }
+
+    /// Runs the stored program as the RUN command would
+    pub fn run_program(&mut self) -> tiny_basic::Result<'_, ()> {
+        self.engine.run(&mut self.interpreter, &self.program)
+    }
+
     pub fn run(&mut self) -> std::io::Result<()> {
         println!("READY");
+        let warning_handler = self.warning_handler;
         loop {
             let line = match Self::read_line()? {
                 Some(line) => line,
@@ -79,42 +128,192 @@ impl<'user_input> Repl {
             let line = unwrap_or_continue!(ascii::AsciiStr::from_ascii(&line));
             let line = unwrap_or_continue!(Line::try_from(line.trim()));
     
-            show_outcome!(match line.index {
+            let warnings = match line.index {
                 Some(i) => {
                     self.insert_or_erase_line(i, line.statement)
                 },
                 None => {
                     self.process_line(line.statement)
                 }
-            });
+            };
+            show_outcome!(warnings.map(|warnings| warnings.iter().for_each(|warning| warning_handler(warning))));
         }
     }
 
-    fn insert_or_erase_line(&mut self, index: types::LineIndex, contents: &'user_input AsciiStr) -> tiny_basic::Result<'user_input, ()> {
+    /// Stores or erases a numbered line, returning the warnings
+    fn insert_or_erase_line(&mut self, index: types::LineIndex, contents: &'user_input AsciiStr) -> tiny_basic::Result<'user_input, Vec<String>> {
         if contents.is_empty() {
             self.program.erase_line(index);
-        } else {
-            self.program.insert_line(index, contents);
+            return Ok(Vec::new());
         }
-        Ok(())
+
+        let warnings = match self.syntax_check {
+            SyntaxCheck::Reject => {
+                syntax_check::check_line(contents, Some(index))?;
+                Vec::new()
+            },
+            SyntaxCheck::Warn => syntax_check::check_line(contents, Some(index))
+                .err()
+                .map(|error| error.to_string())
+                .into_iter()
+                .collect(),
+            SyntaxCheck::Off => Vec::new(),
+        };
+        self.program.insert_line(index, contents);
+        Ok(warnings)
     }
 
-    fn process_line(&'user_input mut self, line: &'user_input AsciiStr) -> tiny_basic::Result<'user_input, ()> {
-        let line = char_stream::AsciiCharStream::from_ascii_str(line);
-        if let Some(command) = line.clone().consume_command() {
+    /// Runs a command or executes a statement, returning the warnings
+    fn process_line(&'user_input mut self, source: &'user_input AsciiStr) -> tiny_basic::Result<'user_input, Vec<String>> {
+        let mut warnings = Vec::new();
+        let tokens = lexer::tokenize(source)?;
+        let line = TokenStream::new(source, &tokens);
+        let mut lookahead = line;
+        if let Some(command) = lookahead.consume_command() {
             match command {
-                char_stream::Command::Run => self.interpreter.run(&self.program)?,
+                char_stream::Command::Run => match Self::consume_line_index(&mut lookahead)? {
+                    Some(line) => self.engine.run_from(&mut self.interpreter, &self.program, line)?,
+                    None => self.engine.run(&mut self.interpreter, &self.program)?,
+                },
+                char_stream::Command::Cont => self.engine.cont(&mut self.interpreter, &self.program)?,
                 char_stream::Command::List => {
-                    for (i, line) in self.program.iter() {
-                        println!("{} {}", i, line);
+                    let lines = Self::consume_line_range(&mut lookahead)?;
+                    let mut pager = Pager::for_stdout();
+                    for (i, line) in self.program.range(lines) {
+                        if !pager.print_line(format_args!("{} {}", i, line)).map_err(TinyBasicErrorKind::from)? {
+                            break;
+                        }
                     }
                 },
-                char_stream::Command::Clear => self.program.clear(),
+                char_stream::Command::Clear => self.interpreter.clear(),
+                char_stream::Command::New => {
+                    self.program.clear();
+                    self.interpreter.clear();
+                },
+                char_stream::Command::Check => self.check_program()?,
+                char_stream::Command::Save => {
+                    let path = Self::consume_file_name(&mut lookahead)?;
+                    std::fs::write(path.as_str(), self.program.to_source())
+                        .map_err(TinyBasicErrorKind::from)?;
+                },
+                char_stream::Command::Load => {
+                    let path = Self::consume_file_name(&mut lookahead)?;
+                    let source = std::fs::read(path.as_str())
+                        .map_err(TinyBasicErrorKind::from)?;
+                    (self.program, warnings) = Self::load_program(&source, self.syntax_check)?;
+                },
+            }
+        } else {
+            let mut lookahead = line;
+            if lookahead.consume_statement().is_some() {
+                self.interpreter.execute(source, &self.program)?;
             }
-        } else if let Some(_) = line.clone().consume_statement() {
-            self.interpreter.execute(&mut line.clone())?;
         }
-        Ok(())
+        Ok(warnings)
+    }
+
+    /// Checks the syntax of every line of the program, returning the errors of all of the bad lines
+    fn check_program(&self) -> tiny_basic::Result<'user_input, ()> {
+        let bad_lines = Self::syntax_errors(&self.program);
+        if bad_lines.is_empty() {
+            Ok(())
+        } else {
+            Err(TinyBasicError::from(TinyBasicErrorKind::SyntaxErrors(bad_lines)))
+        }
+    }
+
+    /// Describes the syntax errors of every bad line of the program
+    fn syntax_errors(program: &ProgramStorage) -> Vec<String> {
+        syntax_check::check_program(program)
+            .iter()
+            .map(|error| error.to_string())
+            .collect()
+    }
+
+    /// Consumes the optional line number, which must end the command
+    fn consume_line_index(stream: &mut TokenStream<'_, 'user_input>) -> tiny_basic::Result<'user_input, Option<types::LineIndex>> {
+        let line = Self::consume_optional_line_index(stream)?;
+        Self::expect_end(stream, line)
+    }
+
+    /// Consumes the lines to list, which must end the command:
+    /// all of them, `N`, `N-M`, `-M` up to M or `N-` from N
+    fn consume_line_range(stream: &mut TokenStream<'_, 'user_input>) -> tiny_basic::Result<'user_input, RangeInclusive<types::LineIndex>> {
+        let first = Self::consume_optional_line_index(stream)?;
+        let lines = if stream.consume_char(AsciiChar::Minus).is_some() {
+            let last = Self::consume_optional_line_index(stream)?;
+            first.unwrap_or(types::LineIndex::FIRST)..=last.unwrap_or(types::LineIndex::LAST)
+        } else {
+            match first {
+                Some(line) => line..=line,
+                None => types::LineIndex::FIRST..=types::LineIndex::LAST,
+            }
+        };
+        Self::expect_end(stream, lines)
+    }
+
+    fn consume_optional_line_index(stream: &mut TokenStream<'_, 'user_input>) -> tiny_basic::Result<'user_input, Option<types::LineIndex>> {
+        let position = *stream;
+        stream
+            .consume_number()
+            .map(|number| {
+                number
+                    .as_str()
+                    .parse::<i16>()
+                    .map_err(|_| TinyBasicErrorKind::InvalidLineIndex)
+                    .and_then(types::LineIndex::try_from)
+                    .map_err(|kind| TinyBasicError::from_context(&position, kind, None))
+            })
+            .transpose()
+    }
+
+    fn expect_end<T>(stream: &TokenStream<'_, 'user_input>, value: T) -> tiny_basic::Result<'user_input, T> {
+        stream
+            .is_empty()
+            .then_some(value)
+            .ok_or(TinyBasicError::from_context(stream, TinyBasicErrorKind::UnexpectedTokensAtEndOfLine, None))
+    }
+
+    fn consume_file_name(stream: &mut TokenStream<'_, 'user_input>) -> tiny_basic::Result<'user_input, &'user_input AsciiStr> {
+        let file_name = stream
+            .consume_string()
+            .ok_or(TinyBasicError::from_context(stream, TinyBasicErrorKind::Expected('"'), None))?;
+        stream
+            .is_empty()
+            .then_some(file_name)
+            .ok_or(TinyBasicError::from_context(stream, TinyBasicErrorKind::UnexpectedTokensAtEndOfLine, None))
+    }
+
+    /// Parses every line of the source, reporting all bad lines at once.
+    /// The statements are then checked like typed-in lines: with [`SyntaxCheck::Reject`]
+    /// the program is only returned if all of them are correct, with [`SyntaxCheck::Warn`]
+    /// their errors are returned as warnings.
+    fn load_program(source: &[u8], syntax_check: SyntaxCheck) -> tiny_basic::Result<'user_input, (ProgramStorage, Vec<String>)> {
+        let source = AsciiStr::from_ascii(source)
+            .map_err(TinyBasicErrorKind::from)?;
+
+        let mut program = ProgramStorage::new();
+        let mut bad_lines = Vec::new();
+        for (i, line) in ProgramStorage::parse_source(source) {
+            match line {
+                Ok((index, contents)) => program.insert_line(index, contents),
+                Err(error) => bad_lines.push((i, error.to_string())),
+            }
+        }
+
+        if !bad_lines.is_empty() {
+            return Err(TinyBasicError::from(TinyBasicErrorKind::ProgramNotLoaded(bad_lines)));
+        }
+
+        let syntax_errors = match syntax_check {
+            SyntaxCheck::Off => Vec::new(),
+            SyntaxCheck::Reject | SyntaxCheck::Warn => Self::syntax_errors(&program),
+        };
+        if syntax_check == SyntaxCheck::Reject && !syntax_errors.is_empty() {
+            Err(TinyBasicError::from(TinyBasicErrorKind::SyntaxErrors(syntax_errors)))
+        } else {
+            Ok((program, syntax_errors))
+        }
     }
 
     fn read_line() -> std::io::Result<Option<String>> {
@@ -126,4 +325,201 @@ impl<'user_input> Repl {
             Ok(Some(line))
         }
     }
-}
\ No newline at end of file
+}
+
+/// Prints an error, preceded by the errors of the lines it sums up
+fn print_error(error: &TinyBasicError<'_>) {
+    match error.get_kind() {
+        TinyBasicErrorKind::ProgramNotLoaded(bad_lines) => {
+            for (i, line_error) in bad_lines {
+                eprintln!("Line {}: {}", i, line_error);
+            }
+        },
+        TinyBasicErrorKind::SyntaxErrors(bad_lines) => {
+            for line_error in bad_lines {
+                eprintln!("{}", line_error);
+            }
+        },
+        _ => (),
+    }
+    eprintln!("{}", error);
+}
+
+/// Prints a listing, waiting for an answer after every page
+struct Pager<R, W> {
+    input: R,
+    output: W,
+    /// Lines per page, `None` if the output is not paged
+    page_length: Option<usize>,
+    printed: usize
+}
+
+/// Height of the screen if the environment does not tell it
+const DEFAULT_SCREEN_HEIGHT: usize = 24;
+
+impl Pager<StdinLock<'static>, Stdout> {
+    /// Pages the standard output by screenfuls if it is a terminal,
+    /// the height of the screen being taken from `LINES`
+    fn for_stdout() -> Self {
+        let screen_height = std::env::var("LINES")
+            .ok()
+            .and_then(|lines| lines.parse::<usize>().ok())
+            .filter(|lines| *lines > 1)
+            .unwrap_or(DEFAULT_SCREEN_HEIGHT);
+        // One line of the screen is left for the prompt
+        let page_length = stdout().is_terminal().then_some(screen_height - 1);
+        Pager::new(stdin().lock(), stdout(), page_length)
+    }
+}
+
+impl<R: BufRead, W: Write> Pager<R, W> {
+    fn new(input: R, output: W, page_length: Option<usize>) -> Self {
+        Self {
+            input,
+            output,
+            page_length,
+            printed: 0
+        }
+    }
+
+    /// Prints a line, returning false once the user asked to stop the listing
+    fn print_line(&mut self, line: std::fmt::Arguments<'_>) -> std::io::Result<bool> {
+        if self.page_length.is_some_and(|page_length| self.printed == page_length) {
+            write!(self.output, "-- More -- (Enter to go on, Q to stop) ")?;
+            self.output.flush()?;
+            let mut answer = String::new();
+            if self.input.read_line(&mut answer)? == 0 || answer.trim().eq_ignore_ascii_case("Q") {
+                return Ok(false);
+            }
+            self.printed = 0;
+        }
+        writeln!(self.output, "{}", line)?;
+        self.printed += 1;
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ascii::AsciiStr;
+
+    use crate::tiny_basic::error::ErrorKind;
+    use crate::tiny_basic::lexer::{self, TokenStream};
+    use crate::tiny_basic::program_storage::ProgramStorage;
+    use crate::tiny_basic::syntax_check::SyntaxCheck;
+    use crate::tiny_basic::types::LineIndex;
+
+    use super::{Pager, Repl};
+
+    /// The first and the last line which the LIST command selects
+    fn list_range(source: &str) -> Result<(i16, i16), ErrorKind> {
+        let source = AsciiStr::from_ascii(source).unwrap();
+        let tokens = lexer::tokenize(source).unwrap();
+        let mut stream = TokenStream::new(source, &tokens);
+        assert!(stream.consume_command().is_some());
+        Repl::consume_line_range(&mut stream)
+            .map(|lines| (i16::from(*lines.start()), i16::from(*lines.end())))
+            .map_err(|error| error.get_kind().clone())
+    }
+
+    #[test]
+    fn test_load_program_reports_bad_lines() {
+        let error = Repl::load_program(b"10 PRINT 1\nPRINT 2\n20 PRINT 3\n0 END", SyntaxCheck::Reject).err().unwrap();
+        let ErrorKind::ProgramNotLoaded(bad_lines) = error.get_kind() else {
+            panic!("Unexpected error: {}", error);
+        };
+        let positions: Vec<_> = bad_lines.iter().map(|(i, _)| *i).collect();
+        assert_eq!(positions, [2, 4]);
+        assert!(bad_lines[0].1.contains("Expected line index"));
+
+        assert!(Repl::load_program(b"10 PRINT 1\n20 PRINT 2", SyntaxCheck::Reject).is_ok());
+    }
+
+    #[test]
+    fn test_load_program_applies_syntax_check() {
+        let source = b"10 PRINT 1\n20 PRINT 1 +\n30 LET = 2";
+        let error = Repl::load_program(source, SyntaxCheck::Reject).err().unwrap();
+        let ErrorKind::SyntaxErrors(bad_lines) = error.get_kind() else {
+            panic!("Unexpected error: {}", error);
+        };
+        assert_eq!(bad_lines.len(), 2);
+
+        let (program, warnings) = Repl::load_program(source, SyntaxCheck::Warn).unwrap();
+        assert_eq!(warnings.len(), 2);
+        assert_eq!(program.iter().count(), 3);
+
+        let (program, warnings) = Repl::load_program(source, SyntaxCheck::Off).unwrap();
+        assert!(warnings.is_empty());
+        assert_eq!(program.iter().count(), 3);
+    }
+
+    #[test]
+    fn test_check_program_reports_bad_lines() {
+        let program = ProgramStorage::load("10 PRINT 1\n20 PRINT 1 +\n30 LET = 2\n40 END").unwrap();
+        let error = Repl::with_program(program).check_program().err().unwrap();
+        let ErrorKind::SyntaxErrors(bad_lines) = error.get_kind() else {
+            panic!("Unexpected error: {}", error);
+        };
+        assert_eq!(bad_lines.len(), 2);
+        assert!(bad_lines[0].contains("20 PRINT 1 +"), "{}", bad_lines[0]);
+        assert!(bad_lines[1].contains("30 LET = 2"), "{}", bad_lines[1]);
+
+        let program = ProgramStorage::load("10 PRINT 1").unwrap();
+        assert!(Repl::with_program(program).check_program().is_ok());
+    }
+
+    #[test]
+    fn test_syntax_check_modes_on_entry() {
+        let index = LineIndex::try_from(10i16).unwrap();
+        let bad_line = AsciiStr::from_ascii("PRINT 1 +").unwrap();
+        for (mode, stored, warned) in [(SyntaxCheck::Reject, false, false), (SyntaxCheck::Warn, true, true), (SyntaxCheck::Off, true, false)] {
+            let mut repl = Repl::new();
+            repl.set_syntax_check(mode);
+            let result = repl.insert_or_erase_line(index, bad_line);
+            assert_eq!(result.is_err(), !stored, "{:?}", mode);
+            assert_eq!(result.is_ok_and(|warnings| warnings.len() == 1), warned, "{:?}", mode);
+            assert_eq!(repl.program.get_line(index).is_some(), stored, "{:?}", mode);
+        }
+    }
+
+    #[test]
+    fn test_list_range() {
+        assert_eq!(list_range("LIST").ok(), Some((1, i16::MAX)));
+        assert_eq!(list_range("LIST 100").ok(), Some((100, 100)));
+        assert_eq!(list_range("LIST 100-200").ok(), Some((100, 200)));
+        assert_eq!(list_range("LIST -50").ok(), Some((1, 50)));
+        assert_eq!(list_range("LIST 300-").ok(), Some((300, i16::MAX)));
+        assert!(matches!(list_range("LIST 1-2-3"), Err(ErrorKind::UnexpectedTokensAtEndOfLine)));
+        assert!(matches!(list_range("LIST X"), Err(ErrorKind::UnexpectedTokensAtEndOfLine)));
+        assert!(matches!(list_range("LIST 99999"), Err(ErrorKind::InvalidLineIndex)));
+        assert!(matches!(list_range("LIST 0-10"), Err(ErrorKind::InvalidLineIndex)));
+    }
+
+    /// Lists lines 1 to 5 with pages of two lines, returning the output
+    fn page(answers: &str) -> String {
+        let mut output = Vec::new();
+        let mut pager = Pager::new(answers.as_bytes(), &mut output, Some(2));
+        for i in 1..=5 {
+            if !pager.print_line(format_args!("{}", i)).unwrap() {
+                break;
+            }
+        }
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn test_pager() {
+        const MORE: &str = "-- More -- (Enter to go on, Q to stop) ";
+        assert_eq!(page("\n\n"), format!("1\n2\n{MORE}3\n4\n{MORE}5\n"));
+        assert_eq!(page("\nq\n"), format!("1\n2\n{MORE}3\n4\n{MORE}"));
+        // The end of the input stops the listing as well
+        assert_eq!(page(""), format!("1\n2\n{MORE}"));
+
+        let mut output = Vec::new();
+        let mut pager = Pager::new("".as_bytes(), &mut output, None);
+        for i in 1..=5 {
+            assert!(pager.print_line(format_args!("{}", i)).unwrap());
+        }
+        assert_eq!(output, b"1\n2\n3\n4\n5\n");
+    }
+}
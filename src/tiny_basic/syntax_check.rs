@@ -0,0 +1,126 @@
+/*
+    Tiny BASIC interpreter written in Rust
+    Copyright (C) 2025 Artyom Makarov
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use ascii::AsciiStr;
+
+use crate::tiny_basic;
+use crate::tiny_basic::parser;
+use crate::tiny_basic::program_storage::ProgramStorage;
+use crate::tiny_basic::types;
+
+/// What happens to a line with a syntax error when it is entered
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum SyntaxCheck {
+    /// The line is not stored
+    #[default]
+    Reject,
+    /// The line is stored, but the error is reported
+    Warn,
+    /// The line is stored without checking
+    Off
+}
+
+impl std::str::FromStr for SyntaxCheck {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "reject" => Ok(SyntaxCheck::Reject),
+            "warn" => Ok(SyntaxCheck::Warn),
+            "off" => Ok(SyntaxCheck::Off),
+            _ => Err(format!("Unknown syntax check mode: {}", s)),
+        }
+    }
+}
+
+/// Checks that a line follows the grammar without executing it.
+/// Every statement is checked, including those after `THEN` of an `IF`.
+/// Errors which depend on the values, such as a type mismatch or an undefined array, are not detected.
+pub fn check_line(line: &AsciiStr, line_number: Option<types::LineIndex>) -> tiny_basic::Result<'_, ()> {
+    parser::parse_line(line, line_number).map(|_| ())
+}
+
+/// Checks every line of a program, returning the errors of all of the bad lines
+pub fn check_program(program: &ProgramStorage) -> Vec<tiny_basic::error::Error<'_>> {
+    program
+        .iter()
+        .filter_map(|(i, line)| check_line(line, Some(*i)).err())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use ascii::AsciiStr;
+
+    use crate::tiny_basic::error::ErrorKind;
+
+    use crate::tiny_basic::program_storage::ProgramStorage;
+
+    use super::{check_line, check_program};
+
+    fn check(line: &str) -> Result<(), (ErrorKind, Option<usize>)> {
+        check_line(AsciiStr::from_ascii(line).unwrap(), None)
+            .map_err(|error| (error.get_kind(), error.get_location()))
+    }
+
+    #[test]
+    fn test_valid_lines() {
+        for line in [
+            "PRINT 1, \"A\", A$ + B$, -(2 + 3) * ABS(-4)",
+            "LET A(I, J + 1) = MAX(1, 2, 3) / 2",
+            "IF A <= 10 THEN PRINT A : GOTO 10",
+            "INPUT A, B$, C(2)",
+            "FOR I = 1 TO 10 STEP -2 : NEXT I : NEXT",
+            "DIM A(10), B$(2, 3)",
+            "RANDOMIZE : RANDOMIZE 42",
+            "GOSUB 100 : RETURN : END",
+            "REM : anything goes here",
+            "PRINT 1 ' and here",
+        ] {
+            assert!(check(line).is_ok(), "{}: {:?}", line, check(line));
+        }
+    }
+
+    #[test]
+    fn test_syntax_errors() {
+        assert!(matches!(check("PRNT 1"), Err((ErrorKind::ExpectedStatement, Some(0)))));
+        assert!(matches!(check("PRINT 1 +"), Err((ErrorKind::FactorCouldNotBeParsed, _))));
+        assert!(matches!(check("LET A 1"), Err((ErrorKind::Expected('='), Some(6)))));
+        assert!(matches!(check("FOR I = 1 10"), Err((ErrorKind::ExpectedKeyword, _))));
+        assert!(matches!(check("DIM A(1"), Err((ErrorKind::Expected(')'), _))));
+        assert!(matches!(check("IF A = 1 PRINT A"), Err((ErrorKind::ExpectedKeyword, _))));
+        assert!(matches!(check("RETURN 10"), Err((ErrorKind::UnexpectedTokensAtEndOfLine, _))));
+        assert!(matches!(check("PRINT \"A"), Err((ErrorKind::Expected('"'), _))));
+    }
+
+    #[test]
+    fn test_check_program() {
+        let program = ProgramStorage::load("10 PRINT 1\n20 PRINT 1 +\n30 END\n40 LET = 2").unwrap();
+        let bad_lines: Vec<_> = check_program(&program)
+            .iter()
+            .map(|error| error.get_line_number().map(i16::from))
+            .collect();
+        assert_eq!(bad_lines, [Some(20), Some(40)]);
+    }
+
+    #[test]
+    fn test_statement_after_false_condition_is_checked() {
+        assert!(matches!(check("IF 1 = 0 THEN PRIN 1"), Err((ErrorKind::ExpectedStatement, Some(14)))));
+        assert!(matches!(check("IF 1 = 0 THEN PRINT 1 : LET = 2"), Err((ErrorKind::ExpectedVariableName, _))));
+    }
+}
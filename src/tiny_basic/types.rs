@@ -16,22 +16,47 @@
     along with this program.  If not, see <https://www.gnu.org/licenses/>.
 */
 
+use ascii::{AsciiChar, AsciiStr, AsciiString};
+
 use crate::tiny_basic;
+use crate::tiny_basic::number::Numeric;
 
+#[cfg(feature = "number-big")]
+pub type Number = tiny_basic::big_int::BigInt;
+#[cfg(all(feature = "number-f64", not(feature = "number-big")))]
+pub type Number = f64;
+#[cfg(all(feature = "number-i64", not(any(feature = "number-f64", feature = "number-big"))))]
+pub type Number = i64;
+#[cfg(all(feature = "number-i32", not(any(feature = "number-i64", feature = "number-f64", feature = "number-big"))))]
+pub type Number = i32;
+#[cfg(not(any(feature = "number-i32", feature = "number-i64", feature = "number-f64", feature = "number-big")))]
 pub type Number = i16;
 
+/// Line numbers are limited to the range of the original Tiny BASIC
+/// regardless of the numeric back-end
 #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
-pub struct LineIndex(Number);
+pub struct LineIndex(i16);
 
 impl LineIndex {
-    pub const MIN: Number = 1;
-    pub const MAX: Number = Number::MAX;
+    pub const MIN: i16 = 1;
+    pub const MAX: i16 = i16::MAX;
+    pub const FIRST: LineIndex = LineIndex(Self::MIN);
+    pub const LAST: LineIndex = LineIndex(Self::MAX);
+
+    /// Converts the value of an expression, e.g. the target of GOTO
+    pub fn from_number(value: Number) -> Result<Self, tiny_basic::error::ErrorKind> {
+        value
+            .to_i64()
+            .and_then(|value| i16::try_from(value).ok())
+            .ok_or(tiny_basic::error::ErrorKind::InvalidLineIndex)?
+            .try_into()
+    }
 }
 
-impl TryFrom<Number> for LineIndex {
+impl TryFrom<i16> for LineIndex {
     type Error = tiny_basic::error::ErrorKind;
 
-    fn try_from(value: Number) -> Result<Self, Self::Error> {
+    fn try_from(value: i16) -> Result<Self, Self::Error> {
         (Self::MIN..=Self::MAX)
             .contains(&value)
             .then_some(LineIndex(value))
@@ -39,15 +64,213 @@ impl TryFrom<Number> for LineIndex {
     }
 }
 
-impl Into<Number> for LineIndex {
-    fn into(self) -> Number {
-        let LineIndex(i) = self;
-        i 
+impl From<LineIndex> for i16 {
+    fn from(value: LineIndex) -> Self {
+        let LineIndex(i) = value;
+        i
     }
 }
 
 impl std::fmt::Display for LineIndex {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", Into::<Number>::into(*self))
+        write!(f, "{}", Into::<i16>::into(*self))
+    }
+}
+
+/// Value of an expression or of a variable
+#[derive(Clone, PartialEq, Debug)]
+pub enum Value {
+    Number(Number),
+    String(AsciiString)
+}
+
+impl Value {
+    /// String variables are distinguished by the trailing `$` in their names
+    pub fn is_string_name(name: &AsciiStr) -> bool {
+        name.last() == Some(AsciiChar::Dollar)
+    }
+
+    /// The value of a variable which has not been assigned yet
+    pub fn default_for(name: &AsciiStr) -> Self {
+        if Self::is_string_name(name) {
+            Value::String(AsciiString::new())
+        } else {
+            Value::Number(Number::zero())
+        }
+    }
+
+    pub fn is_string(&self) -> bool {
+        matches!(self, Value::String(_))
+    }
+}
+
+impl From<Number> for Value {
+    fn from(value: Number) -> Self {
+        Value::Number(value)
+    }
+}
+
+impl std::fmt::Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Value::Number(number) => number.format(f),
+            Value::String(string) => write!(f, "{}", string),
+        }
+    }
+}
+
+/// How the arithmetic operators behave when the result does not fit into a [`Number`].
+/// Division by zero is an error in every mode.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum ArithmeticMode {
+    /// Overflow is an error
+    #[default]
+    Checked,
+    /// The result wraps around, as in the interpreters which relied on the CPU arithmetic
+    Wrapping,
+    /// The result is clamped to the range of [`Number`]
+    Saturating
+}
+
+impl ArithmeticMode {
+    pub fn add(self, lhs: Number, rhs: Number) -> Result<Number, tiny_basic::error::ErrorKind> {
+        match self {
+            ArithmeticMode::Checked => Numeric::checked_add(lhs, rhs).ok_or(tiny_basic::error::ErrorKind::Overflow),
+            ArithmeticMode::Wrapping => Ok(Numeric::wrapping_add(lhs, rhs)),
+            ArithmeticMode::Saturating => Ok(Numeric::saturating_add(lhs, rhs)),
+        }
+    }
+
+    pub fn sub(self, lhs: Number, rhs: Number) -> Result<Number, tiny_basic::error::ErrorKind> {
+        match self {
+            ArithmeticMode::Checked => Numeric::checked_sub(lhs, rhs).ok_or(tiny_basic::error::ErrorKind::Overflow),
+            ArithmeticMode::Wrapping => Ok(Numeric::wrapping_sub(lhs, rhs)),
+            ArithmeticMode::Saturating => Ok(Numeric::saturating_sub(lhs, rhs)),
+        }
+    }
+
+    pub fn mul(self, lhs: Number, rhs: Number) -> Result<Number, tiny_basic::error::ErrorKind> {
+        match self {
+            ArithmeticMode::Checked => Numeric::checked_mul(lhs, rhs).ok_or(tiny_basic::error::ErrorKind::Overflow),
+            ArithmeticMode::Wrapping => Ok(Numeric::wrapping_mul(lhs, rhs)),
+            ArithmeticMode::Saturating => Ok(Numeric::saturating_mul(lhs, rhs)),
+        }
+    }
+
+    pub fn div(self, lhs: Number, rhs: Number) -> Result<Number, tiny_basic::error::ErrorKind> {
+        if rhs == Number::zero() {
+            return Err(tiny_basic::error::ErrorKind::DivisionByZero);
+        }
+        match self {
+            ArithmeticMode::Checked => Numeric::checked_div(lhs, rhs).ok_or(tiny_basic::error::ErrorKind::Overflow),
+            ArithmeticMode::Wrapping => Ok(Numeric::wrapping_div(lhs, rhs)),
+            ArithmeticMode::Saturating => Ok(Numeric::saturating_div(lhs, rhs)),
+        }
+    }
+
+    pub fn neg(self, value: Number) -> Result<Number, tiny_basic::error::ErrorKind> {
+        self.sub(Number::zero(), value)
+    }
+}
+
+impl std::str::FromStr for ArithmeticMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "checked" => Ok(ArithmeticMode::Checked),
+            "wrapping" => Ok(ArithmeticMode::Wrapping),
+            "saturating" => Ok(ArithmeticMode::Saturating),
+            _ => Err(format!("Unknown arithmetic mode: {}", s)),
+        }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use ascii::{AsciiStr, AsciiString};
+
+    use crate::tiny_basic::error::ErrorKind;
+    use crate::tiny_basic::number::Numeric;
+
+    use super::{ArithmeticMode, LineIndex, Number, Value};
+
+    #[test]
+    fn test_line_index_from_number() {
+        let line = |number: Number| LineIndex::from_number(number).map(i16::from).ok();
+        let integer = |value: i64| Number::from_i64(value).unwrap();
+        assert_eq!(line(integer(20)), Some(20));
+        assert_eq!(line(integer(32767)), Some(32767));
+        assert_eq!(line(integer(0)), None);
+        assert_eq!(line(integer(-1)), None);
+        // Wider numbers must still fit into a line number
+        if let Some(number) = Number::from_i64(40000) {
+            assert_eq!(line(number), None);
+        }
+        #[cfg(all(feature = "number-f64", not(feature = "number-big")))]
+        assert_eq!(line(20.9), Some(20));
+        #[cfg(feature = "number-big")]
+        assert_eq!(line("100000000000000000000".parse().unwrap()), None);
+    }
+
+    #[test]
+    fn test_string_values() {
+        let name = |name| AsciiStr::from_ascii(name).unwrap();
+        assert!(Value::is_string_name(name("N$")));
+        assert!(!Value::is_string_name(name("N")));
+        assert_eq!(Value::default_for(name("N$")), Value::String(AsciiString::new()));
+        assert_eq!(Value::default_for(name("N")), Value::Number(Number::zero()));
+
+        let value = Value::String(AsciiString::from_ascii("TINY BASIC").unwrap());
+        assert!(value.is_string());
+        assert_eq!(value.to_string(), "TINY BASIC");
+        assert!(!Value::Number(Number::one()).is_string());
+    }
+
+    #[test]
+    #[cfg(not(any(feature = "number-f64", feature = "number-big")))]
+    fn test_arithmetic_modes() {
+        assert!(matches!(ArithmeticMode::Checked.add(Number::MAX, 1), Err(ErrorKind::Overflow)));
+        assert_eq!(ArithmeticMode::Wrapping.add(Number::MAX, 1).ok(), Some(Number::MIN));
+        assert_eq!(ArithmeticMode::Saturating.add(Number::MAX, 1).ok(), Some(Number::MAX));
+        assert!(matches!(ArithmeticMode::Checked.div(Number::MIN, -1), Err(ErrorKind::Overflow)));
+        assert!(matches!(ArithmeticMode::Checked.neg(Number::MIN), Err(ErrorKind::Overflow)));
+        assert_eq!(ArithmeticMode::Wrapping.neg(Number::MIN).ok(), Some(Number::MIN));
+        assert_eq!(ArithmeticMode::Saturating.neg(Number::MIN).ok(), Some(Number::MAX));
+        for mode in [ArithmeticMode::Checked, ArithmeticMode::Wrapping, ArithmeticMode::Saturating] {
+            assert!(matches!(mode.div(1, 0), Err(ErrorKind::DivisionByZero)));
+            assert_eq!(mode.mul(-6, 7).ok(), Some(-42));
+        }
+    }
+
+    #[test]
+    #[cfg(not(any(feature = "number-i32", feature = "number-i64", feature = "number-f64", feature = "number-big")))]
+    fn test_16_bit_arithmetic_modes() {
+        let results = |mode: ArithmeticMode| [mode.add(32767, 1), mode.mul(200, 200), mode.sub(-32767, 2)].map(Result::ok);
+        assert_eq!(results(ArithmeticMode::Checked), [None, None, None]);
+        assert_eq!(results(ArithmeticMode::Wrapping), [Some(-32768), Some(-25536), Some(32767)]);
+        assert_eq!(results(ArithmeticMode::Saturating), [Some(32767), Some(32767), Some(-32768)]);
+    }
+
+    #[test]
+    #[cfg(all(feature = "number-f64", not(feature = "number-big")))]
+    fn test_floating_point_arithmetic_modes() {
+        assert!(matches!(ArithmeticMode::Checked.mul(Number::MAX, 2.0), Err(ErrorKind::Overflow)));
+        assert_eq!(ArithmeticMode::Wrapping.mul(Number::MAX, 2.0).ok(), Some(Number::MAX));
+        assert_eq!(ArithmeticMode::Saturating.sub(Number::MIN, 1e300).ok(), Some(Number::MIN));
+        assert_eq!(ArithmeticMode::Checked.div(7.0, 2.0).ok(), Some(3.5));
+        assert!(matches!(ArithmeticMode::Checked.div(1.0, 0.0), Err(ErrorKind::DivisionByZero)));
+    }
+
+    #[test]
+    #[cfg(feature = "number-big")]
+    fn test_big_integer_arithmetic_modes() {
+        let big = |text: &str| text.parse::<Number>().unwrap();
+        for mode in [ArithmeticMode::Checked, ArithmeticMode::Wrapping, ArithmeticMode::Saturating] {
+            assert_eq!(mode.add(big("32767"), big("1")).ok(), Some(big("32768")));
+            assert_eq!(mode.mul(big("-4294967296"), big("4294967296")).ok(), Some(big("-18446744073709551616")));
+            assert_eq!(mode.neg(big("-7")).ok(), Some(big("7")));
+            assert!(matches!(mode.div(big("1"), big("0")), Err(ErrorKind::DivisionByZero)));
+        }
+    }
+}
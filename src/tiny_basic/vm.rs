@@ -0,0 +1,320 @@
+/*
+    Tiny BASIC interpreter written in Rust
+    Copyright (C) 2025 Artyom Makarov
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+//! Stack machine executing the [`Bytecode`] of a program.
+//!
+//! The machine works on the variables, the arrays and the console of an [`Interpreter`],
+//! and performs the operations the same way the tree walker does, so both engines
+//! produce the same output and the same errors.
+
+use crate::tiny_basic;
+use crate::tiny_basic::bytecode::{Address, Bytecode, Instruction};
+use crate::tiny_basic::char_stream::AsciiCharStream;
+use crate::tiny_basic::console::Console;
+use crate::tiny_basic::error::{Error as TinyBasicError, ErrorKind as TinyBasicErrorKind};
+use crate::tiny_basic::interpreter::{Interpreter, LoopFrame, PausedState, Target};
+use crate::tiny_basic::number::Numeric;
+use crate::tiny_basic::types;
+
+/// State of a run which the tree walker keeps in the [`Interpreter`] instead
+#[derive(Default)]
+pub(crate) struct Machine {
+    pc: Address,
+    stack: Vec<types::Value>,
+    /// Elements found by [`Instruction::ElementOffset`]
+    offsets: Vec<usize>,
+    return_stack: Vec<Address>,
+    loop_stack: Vec<LoopFrame<Address>>
+}
+
+impl Machine {
+    fn pop(&mut self) -> types::Value {
+        self.stack.pop().expect("Stack should not be empty")
+    }
+
+    fn pop_number(&mut self) -> types::Number {
+        match self.pop() {
+            types::Value::Number(number) => number,
+            types::Value::String(_) => unreachable!("Number should have been checked"),
+        }
+    }
+
+    /// Pops the last `count` values, which must be numbers
+    fn pop_numbers(&mut self, count: u32) -> Vec<types::Number> {
+        let values = self.stack.split_off(self.stack.len() - count as usize);
+        values
+            .into_iter()
+            .map(|value| match value {
+                types::Value::Number(number) => number,
+                types::Value::String(_) => unreachable!("Number should have been checked"),
+            })
+            .collect()
+    }
+}
+
+impl<'line_source, C: Console> Interpreter<C> {
+    /// Runs a compiled program from its first line, after clearing the variables and the stacks
+    pub(crate) fn run_bytecode(&mut self, bytecode: &Bytecode<'line_source>) -> tiny_basic::Result<'line_source, ()> {
+        self.clear();
+        self.take_interrupt();
+        self.run_machine(bytecode, Machine::default())
+    }
+
+    /// Runs a compiled program from a line, after clearing the variables and the stacks
+    pub(crate) fn run_bytecode_from(&mut self, bytecode: &Bytecode<'line_source>, line: types::LineIndex) -> tiny_basic::Result<'line_source, ()> {
+        self.clear();
+        let pc = bytecode
+            .find_line(line)
+            .ok_or(TinyBasicError::from(TinyBasicErrorKind::LineNotFound(line)))?;
+        self.take_interrupt();
+        self.run_machine(bytecode, Machine { pc, ..Machine::default() })
+    }
+
+    pub(crate) fn run_machine(&mut self, bytecode: &Bytecode<'line_source>, mut machine: Machine) -> tiny_basic::Result<'line_source, ()> {
+        loop {
+            let address = machine.pc;
+            match self.step(bytecode, &mut machine) {
+                // Every endless loop jumps back, so the interruption is checked there
+                Ok(true) if machine.pc <= address && self.take_interrupt() => {
+                    let line = bytecode.line_of(machine.pc);
+                    self.pause(PausedState::Bytecode(machine), bytecode.program());
+                    return Err(TinyBasicError::from(TinyBasicErrorKind::Break).set_line_number(line));
+                },
+                Ok(true) => (),
+                Ok(false) => {
+                    // The END of the program may be resumed, unlike the end of the code or a jump to a missing line
+                    if matches!(bytecode.code[address], Instruction::End) && machine.pc < bytecode.code.len() {
+                        self.pause(PausedState::Bytecode(machine), bytecode.program());
+                    }
+                    return Ok(());
+                },
+                Err(error) => {
+                    // STOP resumes with the following statement, an error retries the failed one
+                    if !matches!(error.get_kind(), TinyBasicErrorKind::Break) {
+                        machine.pc = bytecode.statement_start(address);
+                        machine.stack.clear();
+                        machine.offsets.clear();
+                    }
+                    self.pause(PausedState::Bytecode(machine), bytecode.program());
+
+                    let line = bytecode.line_of(address);
+                    let error = match line.and_then(|line| bytecode.program().get_line(line)) {
+                        Some(source) => error.set_context(&AsciiCharStream::from_ascii_str(source)),
+                        None => error,
+                    };
+                    return Err(error.set_line_number(line));
+                },
+            }
+        }
+    }
+
+    /// Executes one instruction, returning whether the program goes on
+    fn step(&mut self, bytecode: &Bytecode<'line_source>, machine: &mut Machine) -> tiny_basic::Result<'line_source, bool> {
+        let instruction = &bytecode.code[machine.pc];
+        machine.pc += 1;
+        match instruction {
+            Instruction::Constant(index) => machine.stack.push(bytecode.constants[*index as usize].clone()),
+            Instruction::Load(name) => machine.stack.push(self.variable(bytecode.string(*name))),
+            Instruction::RequireNumber { location } => {
+                if let Some(types::Value::String(_)) = machine.stack.last() {
+                    return Err(self.error(*location, TinyBasicErrorKind::TypeMismatch));
+                }
+            },
+            Instruction::Unary { operator, operand_location, location } => {
+                let value = machine.pop();
+                let value = self.apply_unary_operator(*operator, value, *operand_location, *location)?;
+                machine.stack.push(value);
+            },
+            Instruction::Binary { operator, operator_location, rhs_location } => {
+                let rhs = machine.pop();
+                let lhs = machine.pop();
+                let value = self.apply_operator(*operator, lhs, rhs, *operator_location, *rhs_location)?;
+                machine.stack.push(value);
+            },
+            Instruction::CheckCallable { name, location } => {
+                let name = bytecode.string(*name);
+                if !self.is_array(name) {
+                    self.function(name, *location)?;
+                }
+            },
+            Instruction::Call { name, arguments, argument_locations, location } => {
+                let name = bytecode.string(*name);
+                let arguments = machine.pop_numbers(*arguments);
+                let value = if self.is_array(name) {
+                    let offset = self.element_offset(name, *location, &arguments, |i| bytecode.location(*argument_locations, i))?;
+                    self.element(name, offset)
+                } else {
+                    let function = self.function(name, *location)?;
+                    self.call_function(function, *location, &arguments)?
+                };
+                machine.stack.push(value);
+            },
+            Instruction::Print(count) => {
+                let values = machine.stack.split_off(machine.stack.len() - *count as usize);
+                self.print(&values)?;
+            },
+            Instruction::JumpUnless { operator, rhs_location, target } => {
+                let rhs = machine.pop();
+                let lhs = machine.pop();
+                if !self.compare(lhs, *operator, rhs, *rhs_location)? {
+                    machine.pc = *target;
+                }
+            },
+            Instruction::Jump(target) => machine.pc = *target,
+            Instruction::JumpToLine => {
+                let line = types::LineIndex::from_number(machine.pop_number())?;
+                match bytecode.find_line(line) {
+                    Some(target) => machine.pc = target,
+                    // As in the tree walker, jumping to a missing line ends the program
+                    None => return Ok(false),
+                }
+            },
+            Instruction::Gosub(target) => {
+                machine.return_stack.push(machine.pc);
+                machine.pc = *target;
+            },
+            Instruction::GosubLine => {
+                let line = types::LineIndex::from_number(machine.pop_number())?;
+                machine.return_stack.push(machine.pc);
+                match bytecode.find_line(line) {
+                    Some(target) => machine.pc = target,
+                    None => return Ok(false),
+                }
+            },
+            Instruction::Return => {
+                machine.pc = machine
+                    .return_stack
+                    .pop()
+                    .ok_or(TinyBasicError::from(TinyBasicErrorKind::ReturnOnEmptyStack))?;
+            },
+            Instruction::ElementOffset { name, subscripts, subscript_locations, location } => {
+                let subscripts = machine.pop_numbers(*subscripts);
+                let offset = self.element_offset(bytecode.string(*name), *location, &subscripts, |i| bytecode.location(*subscript_locations, i))?;
+                machine.offsets.push(offset);
+            },
+            Instruction::StoreVariable { name, value_location } => {
+                let value = machine.pop();
+                self.assign_checked(Target::Variable(bytecode.string(*name).to_owned()), value, *value_location)?;
+            },
+            Instruction::StoreElement { name, value_location } => {
+                let value = machine.pop();
+                let offset = machine.offsets.pop().expect("Element should have been found");
+                self.assign_checked(Target::Element(bytecode.string(*name).to_owned(), offset), value, *value_location)?;
+            },
+            Instruction::InputVariable { name, prompt } => {
+                self.input(Target::Variable(bytecode.string(*name).to_owned()), bytecode.string(*prompt))?;
+            },
+            Instruction::InputElement { name, prompt } => {
+                let offset = machine.offsets.pop().expect("Element should have been found");
+                self.input(Target::Element(bytecode.string(*name).to_owned(), offset), bytecode.string(*prompt))?;
+            },
+            Instruction::Dim { name, bounds, location } => {
+                let bounds = machine.pop_numbers(*bounds);
+                self.dim(bytecode.string(*name), *location, &bounds)?;
+            },
+            Instruction::For { variable, step } => {
+                let step = if *step {
+                    machine.pop_number()
+                } else {
+                    types::Number::one()
+                };
+                let limit = machine.pop_number();
+                let start = machine.pop_number();
+                self.enter_loop(&mut machine.loop_stack, bytecode.string(*variable), start, limit, step, machine.pc);
+            },
+            Instruction::Next { variable, end } => {
+                let variable = variable.map(|variable| bytecode.string(variable));
+                if let Some(body) = self.next_iteration(&mut machine.loop_stack, variable, *end)? {
+                    machine.pc = body;
+                }
+            },
+            Instruction::Randomize { seed_location } => {
+                let seed = seed_location.map(|location| (machine.pop_number(), location));
+                self.randomize(seed)?;
+            },
+            Instruction::Fail { kind, location } => return Err(self.error(*location, kind.clone())),
+            Instruction::SyntaxError => {
+                let line = bytecode.line_of(machine.pc - 1).expect("Syntax error should be in a line");
+                return match bytecode.program().get_statements(line) {
+                    Some(Err(error)) => Err(error),
+                    _ => unreachable!("Line should have a syntax error"),
+                };
+            },
+            Instruction::Stop => return Err(TinyBasicError::from(TinyBasicErrorKind::Break)),
+            Instruction::End => return Ok(false),
+        }
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tiny_basic::bytecode::{Bytecode, Instruction};
+    use crate::tiny_basic::console::BufferConsole;
+    use crate::tiny_basic::engine::{run_differential, Outcome};
+    use crate::tiny_basic::error::ErrorKind;
+    use crate::tiny_basic::interpreter::Interpreter;
+    use crate::tiny_basic::program_storage::ProgramStorage;
+    use crate::tiny_basic::types::{ArithmeticMode, LineIndex};
+
+    fn run_both(source: &str) -> Outcome {
+        let program = ProgramStorage::load(source).unwrap();
+        run_differential(&program, "", ArithmeticMode::default())
+            .unwrap_or_else(|outcomes| panic!("Engines disagree: {:#?}", outcomes))
+    }
+
+    #[test]
+    fn test_jumps() {
+        let program = ProgramStorage::load("10 GOTO 30\n20 GOTO 10 + 20\n30 END").unwrap();
+        let bytecode = Bytecode::compile(&program);
+        assert!(matches!(bytecode.code[0], Instruction::Jump(target) if Some(target) == bytecode.find_line(LineIndex::try_from(30).unwrap())));
+        assert!(bytecode.code.iter().any(|instruction| matches!(instruction, Instruction::JumpToLine)));
+        assert_eq!(bytecode.line_of(1), Some(LineIndex::try_from(20).unwrap()));
+
+        assert_eq!(run_both("10 LET A = 3\n20 GOSUB A * 10 + 20\n30 PRINT 30 : END\n50 PRINT 50 : RETURN").output, "50 \n30 \n");
+        assert_eq!(run_both("10 GOTO 15\n20 PRINT 20").output, "");
+        assert_eq!(run_both("10 FOR I = 1 TO 2 : IF I = 1 THEN PRINT I : NEXT\n20 PRINT 20").output, "1 \n20 \n");
+    }
+
+    #[test]
+    fn test_errors_have_line_numbers() {
+        let program = ProgramStorage::load("10 DIM A(2)\n20 LET A(1) = 1\n30 PRINT A(3)").unwrap();
+        let error = Interpreter::with_console(BufferConsole::new())
+            .run_bytecode(&Bytecode::compile(&program))
+            .err()
+            .unwrap();
+        assert!(matches!(error.get_kind(), ErrorKind::SubscriptOutOfRange));
+        assert_eq!(error.get_line_number(), LineIndex::try_from(30).ok());
+        assert_eq!(error.get_location(), Some(8));
+
+        for source in [
+            "10 PRINT 1\n20 PRINT 1 +",
+            "10 FOR I$ = 1 TO 2",
+            "10 GOTO 0",
+            "10 PRINT FOO(1 / 0)",
+            "10 LET A = 1 / 0\n",
+            "10 RETURN",
+            "10 NEXT",
+            "10 LET A$ = 1",
+            "10 PRINT -\"A\"",
+            "10 PRINT 1 : STOP : PRINT 2",
+        ] {
+            assert!(run_both(source).error.is_some(), "{}", source);
+        }
+    }
+}